@@ -1,4 +1,4 @@
-import init, { paniky, set_panic_hook_js, ExampleStruct, TreeDataWrapper } from './wasm_hashlife.js';
+import init, { paniky, set_panic_hook_js, ExampleStruct, TreeDataWrapper, TreeSyncReceiver } from './wasm_hashlife.js';
 
 async function run() {
     await init();
@@ -11,6 +11,9 @@ const RLE_STR = (
 );
 var tree = TreeDataWrapper.make_from_rle(RLE_STR);
 var workerhashcount = 0;
+var sync_receiver = TreeSyncReceiver.new();
+// counts loaded patterns, deltas the worker sent for an older one are dropped
+var sync_epoch = 0;
 var canvas = document.getElementById("game-of-life-canvas");
 var xsize = window.innerWidth;
 var ysize = window.innerHeight;
@@ -19,7 +22,9 @@ var ystart = 0;
 var brightnessSelect = document.getElementById("brightness-select");
 var garbageSelect = document.getElementById("garbage-select");
 var filedata = RLE_STR;
-var xyfilecoord = [12,8];
+var loaded_rle = RLE_STR;
+// top left corner and size of the loaded pattern
+var pattern_bounds = [0,0,12,8];
 var inputFileLoader = document.getElementById("rle-file-input");
 var resetBoundingButton = document.getElementById("reset-bounding-box")
 var downloadButton = document.getElementById("download-rle")
@@ -143,14 +148,29 @@ function handle_wheel(event){
     event.stopPropagation();
 }
 function handleRleUpdate(filedata){
+    //parse locally first so that a bad file leaves the current pattern running
+    var newtree;
+    try{
+        newtree = TreeDataWrapper.make_from_rle(filedata);
+    }
+    catch(err){
+        alert("Could not load pattern: " + err);
+        return;
+    }
+    sync_epoch += 1;
     myWorker.postMessage({
         type: "set_rle",
         data: filedata,
+        epoch: sync_epoch,
     });
     //make sure to keep a local copy at all times
     tree.free()
-    tree = TreeDataWrapper.make_from_rle(filedata);
-    parseBoundingBox(filedata)
+    tree = newtree;
+    //the old node ids refer to the old tree, the worker's next delta resends everything
+    sync_receiver.free()
+    sync_receiver = TreeSyncReceiver.new();
+    loaded_rle = filedata;
+    parseBoundingBox()
     resetBoundingBox()
     current_speed = 1;
     current_fps = 4;
@@ -166,14 +186,19 @@ function handleFileUpload() {
     }
     reader.readAsText(file);
 }
-function parseBoundingBox(filedata){
-    var boundsline = filedata.split('\n').filter((l)=>l[0] != "#")[0]
-    const numregex = /\d+/g;
-    xyfilecoord = boundsline.match(numregex);
+function parseBoundingBox(){
+    //taken from the loaded tree, so every file format and position works
+    var box = tree.bounding_box();
+    if (box.length == 4){
+        pattern_bounds = [box[0], box[1], box[2] - box[0] + 1, box[3] - box[1] + 1];
+    }
+    else{
+        pattern_bounds = [0,0,1,1];
+    }
 }
 function resetBoundingBox(){
-    var filex = xyfilecoord[0];
-    var filey = xyfilecoord[1];
+    var filex = pattern_bounds[2];
+    var filey = pattern_bounds[3];
     // console.log(filex);
     // console.log(filey);
     xstart = -filex/4;
@@ -186,8 +211,8 @@ function resetBoundingBox(){
     var scale = zoomScale();
     let xcen = scale*canvas.width/2;
     let ycen = scale*canvas.height/2;
-    xstart = filex / 2 - xcen;
-    ystart = filey / 2 - ycen;
+    xstart = pattern_bounds[0] + filex / 2 - xcen;
+    ystart = pattern_bounds[1] + filey / 2 - ycen;
     brightnessSelect.value = Math.max(1,Math.floor(zoom_level)*4)
     render()
 }
@@ -204,7 +229,7 @@ function downloadText(text, filename){
   document.body.removeChild(element);
 }
 function downloadRLE(){
-    downloadText(tree.get_rle(),filename+"."+tree.get_age());
+    downloadText(tree.get_rle_with_metadata(loaded_rle),filename+"."+tree.get_age());
 }
 function onWindowResize(event){
     xsize = window.innerWidth;
@@ -221,15 +246,25 @@ function handleWebWorker(e){
         myWorker.postMessage({
             type: "set_rle",
             data: RLE_STR,
+            epoch: sync_epoch,
         });
         //initialize web worker garbage select value
         handleGarbageSelect();
         //start calc-render loop
         renderLoop()
     }
-    if (workerData.type === "serialized_tree"){
-        tree.free();
-        tree = TreeDataWrapper.deserialize_treerepr(workerData.data);
+    if (workerData.type === "tree_delta"){
+        try{
+            if (workerData.epoch === sync_epoch){
+                tree.apply_delta(sync_receiver, workerData.data);
+            }
+        }
+        catch(err){
+            console.log("could not apply tree update, requesting a full copy: " + err);
+            myWorker.postMessage({
+                type: "resync",
+            });
+        }
         workerhashcount = workerData.hash_count;
         render();
         renderLoop();
@@ -334,6 +369,7 @@ canvas.height = ysize;
 
 set_panic_hook_js();
 clearCanvas()
+parseBoundingBox()
 resetBoundingBox()
 
 //request first render
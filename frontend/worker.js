@@ -1,4 +1,4 @@
-import init, { paniky, set_panic_hook_js, ExampleStruct, TreeDataWrapper } from './wasm_hashlife.js';
+import init, { paniky, set_panic_hook_js, ExampleStruct, TreeDataWrapper, TreeSyncSender } from './wasm_hashlife.js';
 
 async function run() {
     await init();
@@ -8,15 +8,28 @@ async function run() {
     // import {paniky, set_panic_hook_js, ExampleStruct, TreeDataWrapper} from "wasm-game-of-life";
     set_panic_hook_js();
     var tree = TreeDataWrapper.new();
+    // tracks which nodes the main thread already has, so only new ones are posted
+    var sync_sender = TreeSyncSender.new();
     var hashsize_limit = 1e40;
+    // pattern the deltas belong to, so the main thread can drop ones for a replaced pattern
+    var epoch = 0;
 
     self.onmessage = function(e) {
         const workerData = e.data;
         // console.log("Message from main")
         // console.log(workerData.type)
         if (workerData.type === "set_rle"){
-            tree.free();
-            tree = TreeDataWrapper.make_from_rle(workerData.data);
+            try{
+                const newtree = TreeDataWrapper.make_from_rle(workerData.data);
+                tree.free();
+                tree = newtree;
+            }
+            catch(err){
+                console.log("could not load pattern: " + err);
+            }
+            //the next delta is a reset delta for the new epoch
+            epoch = workerData.epoch;
+            sync_sender.reset();
         }
         else if (workerData.type == "step_forward"){
             tree.step_forward(workerData.amount)
@@ -26,16 +39,20 @@ async function run() {
                 const newtree = tree.pruned_tree();
                 tree.free();
                 tree = newtree;
+                // lets the main thread drop the collected nodes as well
+                sync_sender.reset();
                 console.log("fin size", tree.hash_count())
             }
-            var pruned_tree = tree.pruned_tree();
-            var serialized = pruned_tree.serialize_treerepr();
+            var delta = tree.serialize_delta(sync_sender);
             self.postMessage({
-                type: "serialized_tree",
-                data: serialized,
+                type: "tree_delta",
+                data: delta,
+                epoch: epoch,
                 hash_count: tree.hash_count(),
             });
-            pruned_tree.free();
+        }
+        else if (workerData.type == "resync"){
+            sync_sender.reset();
         }
         else if (workerData.type == "set_garbage_limit"){
             hashsize_limit = workerData.amount;
@@ -43,6 +60,7 @@ async function run() {
                 const newtree = tree.pruned_tree();
                 tree.free();
                 tree = newtree;
+                sync_sender.reset();
             }
         }
         else{
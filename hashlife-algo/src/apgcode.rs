@@ -0,0 +1,246 @@
+/*
+apgcodes, the names Catagolue and apgsearch give to objects, e.g. `xs4_33`
+for the block or `xq4_153` for the glider.
+
+The part after the underscore is the extended Wechsler format: the pattern
+is cut into strips 5 rows high, every column of a strip is one character
+from `0`-`9a`-`v` holding its 5 cells (top row in the lowest bit), strips
+are separated by `z`, trailing empty columns are left out and runs of
+empty columns are shortened to `w` (2), `x` (3) and `y0`-`yz` (4 to 39).
+The canonical code is the shortest, then alphabetically first, encoding
+over all 8 orientations and, for oscillators and spaceships, all phases.
+The prefix is `xs` and the population for still lifes, `xp` and the period
+for oscillators and `xq` and the period for spaceships.
+*/
+
+use crate::parse_error::{ParseError, ParseErrorKind};
+use crate::point::Point;
+use crate::quadtree::TreeData;
+
+const COLUMN_CHARS: &[u8; 32] = b"0123456789abcdefghijklmnopqrstuv";
+const RUN_CHARS: &[u8; 36] = b"0123456789abcdefghijklmnopqrstuvwxyz";
+const STRIP_HEIGHT: i64 = 5;
+
+// the 8 rotations and reflections of the plane
+pub(crate) fn orientations(points: &[Point]) -> [Vec<Point>; 8]{
+    let transforms: [fn(Point) -> Point; 8] = [
+        |p|Point{x: p.x, y: p.y},
+        |p|Point{x: -p.y, y: p.x},
+        |p|Point{x: -p.x, y: -p.y},
+        |p|Point{x: p.y, y: -p.x},
+        |p|Point{x: -p.x, y: p.y},
+        |p|Point{x: p.x, y: -p.y},
+        |p|Point{x: p.y, y: p.x},
+        |p|Point{x: -p.y, y: -p.x},
+    ];
+    transforms.map(|t|points.iter().map(|p|t(*p)).collect())
+}
+
+fn push_zero_run(code: &mut String, mut zeros: usize){
+    while zeros > 0{
+        let run = std::cmp::min(zeros, 39);
+        match run{
+            1 => code.push('0'),
+            2 => code.push('w'),
+            3 => code.push('x'),
+            _ => {
+                code.push('y');
+                code.push(RUN_CHARS[run - 4] as char);
+            }
+        }
+        zeros -= run;
+    }
+}
+
+// extended Wechsler encoding of the points as they are oriented, empty for no points
+pub fn wechsler(points: &[Point]) -> String{
+    if points.is_empty(){
+        return String::new();
+    }
+    let minx = points.iter().map(|p|p.x).min().unwrap();
+    let miny = points.iter().map(|p|p.y).min().unwrap();
+    let maxx = points.iter().map(|p|p.x).max().unwrap();
+    let maxy = points.iter().map(|p|p.y).max().unwrap();
+    let width = (maxx - minx + 1) as usize;
+    let n_strips = ((maxy - miny) / STRIP_HEIGHT + 1) as usize;
+    let mut columns = vec![0u8; width * n_strips];
+    for p in points.iter(){
+        let y = p.y - miny;
+        let strip = (y / STRIP_HEIGHT) as usize;
+        columns[strip * width + (p.x - minx) as usize] |= 1 << (y % STRIP_HEIGHT);
+    }
+    let mut code = String::new();
+    for (i, strip) in columns.chunks(width).enumerate(){
+        if i != 0{
+            code.push('z');
+        }
+        let mut zeros = 0;
+        for column in strip.iter(){
+            if *column == 0{
+                zeros += 1;
+            }
+            else{
+                push_zero_run(&mut code, zeros);
+                zeros = 0;
+                code.push(COLUMN_CHARS[*column as usize] as char);
+            }
+        }
+    }
+    code
+}
+
+fn is_better(code: &str, best: &Option<String>) -> bool{
+    match best{
+        None => true,
+        Some(best) => (code.len(), code) < (best.len(), best.as_str()),
+    }
+}
+
+// shortest, then alphabetically first, encoding over all phases and orientations
+pub fn canonical_wechsler(phases: &[Vec<Point>]) -> String{
+    let mut best: Option<String> = None;
+    for phase in phases.iter(){
+        for oriented in orientations(phase).iter(){
+            let code = wechsler(oriented);
+            if is_better(&code, &best){
+                best = Some(code);
+            }
+        }
+    }
+    best.unwrap_or_default()
+}
+
+// cells of an apgcode, or of a bare extended Wechsler string, with the top left at (0, 0)
+pub fn decode_apgcode(code: &str) -> Result<Vec<Point>, ParseError>{
+    let body = match code.split_once('_'){
+        Some((prefix, body)) => {
+            let valid = ["xs", "xp", "xq"].iter().any(|p|{
+                prefix.strip_prefix(p).is_some_and(|n|!n.is_empty() && n.bytes().all(|b|b.is_ascii_digit()))
+            });
+            if !valid{
+                return Err(ParseError::new(1, 1, ParseErrorKind::InvalidApgcode(format!("unknown prefix '{}'", prefix))));
+            }
+            body
+        },
+        None => code,
+    };
+    let first_column = code.len() - body.len() + 1;
+    let mut points = Vec::new();
+    let mut x = 0;
+    let mut strip = 0;
+    let mut chars = body.chars().enumerate();
+    while let Some((i, c)) = chars.next(){
+        match c{
+            'w' => x += 2,
+            'x' => x += 3,
+            'y' => match chars.next(){
+                Some((_, n)) if n.is_ascii_digit() || n.is_ascii_lowercase() => {
+                    x += 4 + n.to_digit(36).unwrap() as i64;
+                },
+                Some((j, n)) => return Err(ParseError::new(1, first_column + j, ParseErrorKind::InvalidApgcode(format!("'{}' is not a run length", n)))),
+                None => return Err(ParseError::new(1, first_column + i, ParseErrorKind::InvalidApgcode("'y' is missing its run length".to_string()))),
+            },
+            'z' => {
+                x = 0;
+                strip += 1;
+            },
+            c if c.is_ascii_digit() || ('a'..='v').contains(&c) => {
+                let column = c.to_digit(32).unwrap();
+                for bit in 0..STRIP_HEIGHT{
+                    if column & (1 << bit) != 0{
+                        points.push(Point{x, y: strip*STRIP_HEIGHT + bit});
+                    }
+                }
+                x += 1;
+            },
+            c => return Err(ParseError::new(1, first_column + i, ParseErrorKind::InvalidApgcode(format!("unexpected character '{}'", c)))),
+        }
+    }
+    if let Some(n) = code.strip_prefix("xs").and_then(|c|c.split_once('_')).map(|(n, _)|n){
+        if n.parse::<usize>().ok() != Some(points.len()){
+            return Err(ParseError::new(1, 1, ParseErrorKind::InvalidApgcode(format!("population {} does not match the {} cells", n, points.len()))));
+        }
+    }
+    Ok(points)
+}
+
+impl TreeData{
+    // canonical apgcode, None if the pattern dies out or does not repeat within `max_period` generations
+    pub fn apgcode(&self, max_period: u64) -> Option<String>{
+        let mut tree = self.pruned_tree();
+        let periodicity = tree.find_period(max_period)?;
+        let mut phases = Vec::with_capacity(periodicity.period as usize);
+        for _ in 0..periodicity.period{
+            phases.push(tree.dump_all_points());
+            tree.step_forward(1);
+        }
+        let prefix = if periodicity.is_spaceship(){
+            format!("xq{}", periodicity.period)
+        }
+        else if periodicity.period > 1{
+            format!("xp{}", periodicity.period)
+        }
+        else{
+            format!("xs{}", phases[0].len())
+        };
+        Some(format!("{}_{}", prefix, canonical_wechsler(&phases)))
+    }
+    pub fn from_apgcode(code: &str) -> Result<TreeData, ParseError>{
+        decode_apgcode(code).map(|points|TreeData::gather_all_points(&points))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    // Note this useful idiom: importing names from outer (for mod tests) scope.
+    use super::*;
+    use crate::rle::parse_rle;
+
+    fn still_code(rle: &str) -> String{
+        canonical_wechsler(&[parse_rle(rle).unwrap().points])
+    }
+    #[test]
+    fn test_still_life_codes() {
+        assert_eq!(still_code("x = 2, y = 2\n2o$2o!\n"), "33");
+        assert_eq!(still_code("x = 4, y = 3\nb2o$o2bo$b2o!\n"), "696");
+        assert_eq!(still_code("x = 3, y = 3\n2o$obo$bo!\n"), "253");
+        assert_eq!(still_code("x = 4, y = 4\nb2o$o2bo$bobo$2bo!\n"), "2596");
+    }
+    #[test]
+    fn test_zero_runs() {
+        let row = |xs: &[i64]|xs.iter().map(|x|Point{x: *x, y: 0}).collect::<Vec<Point>>();
+        assert_eq!(wechsler(&row(&[0, 2])), "101");
+        assert_eq!(wechsler(&row(&[0, 3])), "1w1");
+        assert_eq!(wechsler(&row(&[0, 4])), "1x1");
+        assert_eq!(wechsler(&row(&[0, 5])), "1y01");
+        assert_eq!(wechsler(&row(&[0, 41])), "1yz01");
+        // a blank strip between two cells 10 rows apart
+        assert_eq!(wechsler(&[Point{x:0, y:0}, Point{x:0, y:10}]), "1zz1");
+    }
+    #[test]
+    fn test_apgcode() {
+        let code = |rle: &str|TreeData::from_pattern(&parse_rle(rle).unwrap()).apgcode(16);
+        assert_eq!(code("x = 3, y = 3\nbo$2bo$3o!\n").as_deref(), Some("xq4_153"));
+        assert_eq!(code("x = 4, y = 2\nb3o$3o!\n").as_deref(), Some("xp2_7e"));
+        // the cells of a domino die straight away
+        assert_eq!(code("x = 2, y = 1\n2o!\n"), None);
+    }
+    #[test]
+    fn test_decode_round_trip() {
+        for code in ["xs4_33", "xs6_696", "xs7_2596", "xp2_7", "xp2_318c", "xq4_153", "xq4_6frc",
+                "xp15_4r4z4r4", "xp3_co9nas0san9oczgoldlo0oldlogz1047210127401"]{
+            let tree = TreeData::from_apgcode(code).unwrap();
+            assert_eq!(tree.apgcode(16).unwrap(), code);
+        }
+    }
+    #[test]
+    fn test_decode_errors() {
+        let invalid = |msg: &str|ParseErrorKind::InvalidApgcode(msg.to_string());
+        assert_eq!(decode_apgcode("xs4_3!").err().unwrap(), ParseError::new(1, 6, invalid("unexpected character '!'")));
+        assert_eq!(decode_apgcode("xs5_33").err().unwrap().kind, invalid("population 5 does not match the 4 cells"));
+        assert_eq!(decode_apgcode("ab_33").err().unwrap().kind, invalid("unknown prefix 'ab'"));
+        assert_eq!(decode_apgcode("33y").err().unwrap(), ParseError::new(1, 3, invalid("'y' is missing its run length")));
+        assert_eq!(decode_apgcode("xs4_3yA3").err().unwrap(), ParseError::new(1, 7, invalid("'A' is not a run length")));
+        assert_eq!(decode_apgcode("33y").err().unwrap().to_string(), "line 1, column 3: invalid apgcode: 'y' is missing its run length");
+    }
+}
@@ -0,0 +1,150 @@
+/*
+Cell-wise boolean combination of two universes.
+
+Both trees are framed onto the same square and the nodes of the second
+are copied into the map of the result, so that equal nodes from either
+side share their key. The combination then recurses over pairs of nodes
+and stops as soon as one side is empty or both sides are the same node,
+so it only visits the parts of the patterns that actually differ.
+*/
+
+use std::collections::HashMap;
+
+use crate::frame::{node_size, WindowCache};
+use crate::point::Point;
+use crate::quadtree::TreeData;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum BoolOp{
+    // cells alive in either pattern
+    Union,
+    // cells alive in both
+    Intersection,
+    // cells alive in the first but not the second
+    Difference,
+    // cells alive in exactly one
+    Xor,
+}
+
+impl BoolOp{
+    fn apply_leaf(&self, a: u64, b: u64) -> u64{
+        match self{
+            BoolOp::Union => a | b,
+            BoolOp::Intersection => a & b,
+            BoolOp::Difference => a & !b,
+            BoolOp::Xor => a ^ b,
+        }
+    }
+}
+
+impl TreeData{
+    fn combine_nodes(&mut self, a: u128, b: u128, depth: u64, op: BoolOp, cache: &mut HashMap<(u128, u128), u128>) -> u128{
+        if depth == 0{
+            return op.apply_leaf(a as u64, b as u64) as u128;
+        }
+        let empty_a = self.is_empty_node(a, depth);
+        let empty_b = self.is_empty_node(b, depth);
+        let shortcut = match op{
+            BoolOp::Union if empty_a || a == b => Some(b),
+            BoolOp::Union if empty_b => Some(a),
+            BoolOp::Intersection if empty_a || a == b => Some(a),
+            BoolOp::Intersection if empty_b => Some(b),
+            BoolOp::Difference if empty_a || empty_b => Some(a),
+            BoolOp::Difference if a == b => Some(self.black_key(depth as usize)),
+            BoolOp::Xor if empty_a => Some(b),
+            BoolOp::Xor if empty_b => Some(a),
+            BoolOp::Xor if a == b => Some(self.black_key(depth as usize)),
+            _ => None,
+        };
+        if let Some(key) = shortcut{
+            return key;
+        }
+        if let Some(key) = cache.get(&(a, b)){
+            return *key;
+        }
+        let (children_a, children_b) = (self.children(a), self.children(b));
+        let mut arr = [0; 4];
+        for (i, child) in arr.iter_mut().enumerate(){
+            *child = self.combine_nodes(children_a[i], children_b[i], depth - 1, op, cache);
+        }
+        let key = self.add_array(arr);
+        cache.insert((a, b), key);
+        key
+    }
+    // combines this universe with `other` moved by `offset`, into a new tree
+    pub fn combine(&self, other: &TreeData, offset: Point, op: BoolOp) -> TreeData{
+        let mut res = self.pruned_tree();
+        let mut other = other.pruned_tree();
+        other.translate(offset);
+        // the smallest square, from the top left of both roots, covering both
+        let top_left = Point{x: std::cmp::min(res.offset.x, other.offset.x), y: std::cmp::min(res.offset.y, other.offset.y)};
+        let far = |t: &TreeData|t.offset + Point{x: node_size(t.depth), y: node_size(t.depth)} + top_left.neg();
+        let (far_res, far_other) = (far(&res), far(&other));
+        let extent = *[far_res.x, far_res.y, far_other.x, far_other.y].iter().max().unwrap();
+        let mut depth = 1;
+        while node_size(depth) < extent{
+            depth += 1;
+        }
+        let key_res = res.frame_key(top_left, depth, &mut WindowCache::new());
+        let key_other = other.frame_key(top_left, depth, &mut WindowCache::new());
+        TreeData::add_deps_to_tree(&other.map, &mut res.map, key_other);
+        res.root = res.combine_nodes(key_res, key_other, depth, op, &mut HashMap::new());
+        res.depth = depth;
+        res.offset = top_left;
+        res
+    }
+    pub fn union(&self, other: &TreeData, offset: Point) -> TreeData{
+        self.combine(other, offset, BoolOp::Union)
+    }
+    pub fn intersection(&self, other: &TreeData, offset: Point) -> TreeData{
+        self.combine(other, offset, BoolOp::Intersection)
+    }
+    pub fn difference(&self, other: &TreeData, offset: Point) -> TreeData{
+        self.combine(other, offset, BoolOp::Difference)
+    }
+    pub fn xor(&self, other: &TreeData, offset: Point) -> TreeData{
+        self.combine(other, offset, BoolOp::Xor)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    // Note this useful idiom: importing names from outer (for mod tests) scope.
+    use super::*;
+    use std::collections::HashSet;
+
+    fn points(seed: i64, n: i64) -> Vec<Point>{
+        // a scattering of cells over a 40x40 square
+        (0..n).map(|i|Point{x: (i*7 + seed*13) % 40 - 20, y: (i*i*3 + seed) % 40 - 20}).collect()
+    }
+    fn cells(tree: &TreeData) -> HashSet<Point>{
+        tree.dump_all_points().into_iter().collect()
+    }
+    #[test]
+    fn test_ops_match_sets() {
+        let (pa, pb) = (points(1, 120), points(5, 90));
+        let (a, b) = (TreeData::gather_all_points(&pa), TreeData::gather_all_points(&pb));
+        let offset = Point{x: 11, y: -3};
+        let sa: HashSet<Point> = pa.iter().copied().collect();
+        let sb: HashSet<Point> = pb.iter().map(|p|*p + offset).collect();
+        assert_eq!(cells(&a.union(&b, offset)), &sa | &sb);
+        assert_eq!(cells(&a.intersection(&b, offset)), &sa & &sb);
+        assert_eq!(cells(&a.difference(&b, offset)), &sa - &sb);
+        assert_eq!(cells(&a.xor(&b, offset)), &sa ^ &sb);
+        assert!(!(&sa & &sb).is_empty());
+    }
+    #[test]
+    fn test_identical_and_empty() {
+        let a = TreeData::gather_all_points(&points(2, 60));
+        assert_eq!(a.xor(&a, Point{x: 0, y: 0}).num_live_cells(), 0);
+        assert_eq!(a.intersection(&a, Point{x: 0, y: 0}).num_live_cells(), a.num_live_cells());
+        let far = a.union(&TreeData::new(), Point{x: 1000, y: 1000});
+        assert_eq!(cells(&far), cells(&a));
+        // the result is a proper universe that can be run on
+        let mut pasted = a.union(&a, Point{x: 300, y: 0});
+        pasted.step_forward(5);
+        let mut single = a.pruned_tree();
+        single.step_forward(5);
+        assert_eq!(pasted.num_live_cells(), 2*single.num_live_cells());
+    }
+}
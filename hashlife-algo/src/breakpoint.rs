@@ -0,0 +1,203 @@
+/*
+Running a universe until a condition holds, landing on the first
+generation at which it does.
+
+Dying out and becoming periodic stay true once they hold, so the first
+generation for them is found with doubling jumps and bisection, like the
+stabilisation search. The other conditions can flip back and forth, but
+nothing travels faster than one cell per generation: a region with no
+live cells within `k` cells of it stays empty for `k` more generations,
+and a pattern `k` cells inside a rectangle cannot leave it sooner. The
+universe jumps that far at once. Population thresholds give no such
+bound and are checked every generation.
+*/
+
+use crate::point::Point;
+use crate::quadtree::TreeData;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum StopCondition{
+    PopulationAbove(u64),
+    PopulationBelow(u64),
+    // a live cell in the rectangle with this top left corner, width and height
+    RegionOccupied(Point, i64, i64),
+    // a live cell outside the rectangle with this top left corner, width and height
+    LeavesRect(Point, i64, i64),
+    DiesOut,
+    // the whole pattern repeats, possibly displaced, within this many generations
+    Periodic(u64),
+}
+
+impl StopCondition{
+    fn is_monotone(&self) -> bool{
+        matches!(self, StopCondition::DiesOut | StopCondition::Periodic(_))
+    }
+    fn holds(&self, tree: &TreeData) -> bool{
+        match self{
+            StopCondition::PopulationAbove(n) => tree.num_live_cells() > *n,
+            StopCondition::PopulationBelow(n) => tree.num_live_cells() < *n,
+            StopCondition::RegionOccupied(top_left, width, height) => tree.population_in(*top_left, *width, *height) > 0,
+            StopCondition::LeavesRect(top_left, width, height) => {
+                tree.population_in(*top_left, *width, *height) < tree.num_live_cells()
+            },
+            StopCondition::DiesOut => tree.num_live_cells() == 0,
+            StopCondition::Periodic(max_period) => {
+                let mut tree = tree.pruned_tree();
+                let age = tree.get_age();
+                tree.num_live_cells() > 0 && tree.find_period(*max_period).is_some_and(|p|p.start == age)
+            },
+        }
+    }
+    // generations the condition is sure to stay false for, given that it is false now
+    fn safe_jump(&self, tree: &TreeData) -> u64{
+        match self{
+            StopCondition::RegionOccupied(top_left, width, height) => {
+                // grow the region in doubling steps for as long as it stays empty
+                let mut safe = 0;
+                let mut k = 1;
+                while k <= (1 << 40) && tree.population_in(*top_left + Point{x: -k, y: -k}, width + 2*k, height + 2*k) == 0{
+                    safe = k;
+                    k *= 2;
+                }
+                std::cmp::max(safe, 1) as u64
+            },
+            StopCondition::LeavesRect(top_left, width, height) => {
+                let (min, max) = match tree.bounding_box(){
+                    Some(bbox) => bbox,
+                    None => return u64::MAX,
+                };
+                let margin = *[
+                    min.x - top_left.x,
+                    min.y - top_left.y,
+                    top_left.x + width - 1 - max.x,
+                    top_left.y + height - 1 - max.y,
+                ].iter().min().unwrap();
+                std::cmp::max(margin, 1) as u64
+            },
+            _ => 1,
+        }
+    }
+}
+
+impl TreeData{
+    // first generation, at most `limit` on, at which a condition that stays true once
+    // it holds is true, by doubling jumps and bisection
+    fn first_monotone(&self, condition: &StopCondition, limit: u64) -> Option<u64>{
+        if condition.holds(self){
+            return Some(0);
+        }
+        let mut before = self.pruned_tree();
+        let mut jump = 1;
+        let mut after = loop{
+            let gen = before.get_age() - self.get_age();
+            if gen >= limit{
+                return None;
+            }
+            let mut next = before.pruned_tree();
+            next.step_forward(std::cmp::min(jump, limit - gen));
+            if condition.holds(&next){
+                break next;
+            }
+            before = next;
+            jump *= 2;
+        };
+        while after.get_age() > before.get_age() + 1{
+            let mut mid = before.pruned_tree();
+            mid.step_forward((after.get_age() - before.get_age()) / 2);
+            if condition.holds(&mid){
+                after = mid;
+            }
+            else{
+                before = mid;
+            }
+        }
+        Some(after.get_age() - self.get_age())
+    }
+    // runs until one of the conditions holds, or for `max_generations`, and returns the
+    // index of the condition that stopped it. The tree is left at the stopping generation.
+    pub fn step_until(&mut self, conditions: &[StopCondition], max_generations: u64) -> Option<usize>{
+        // the monotone conditions are settled up front, bounding how far the rest are run
+        let mut limit = max_generations;
+        let mut first_monotone = None;
+        for (i, condition) in conditions.iter().enumerate(){
+            if condition.is_monotone(){
+                if let Some(gen) = self.first_monotone(condition, limit){
+                    if first_monotone.is_none() || gen < limit{
+                        limit = gen;
+                        first_monotone = Some(i);
+                    }
+                }
+            }
+        }
+        let start = self.age;
+        loop{
+            let gen = self.age - start;
+            // monotone conditions are only checked again where the first of them was found to hold
+            let met = conditions.iter().position(|c|{
+                if c.is_monotone() {first_monotone.is_some() && gen == limit && c.holds(self)} else {c.holds(self)}
+            });
+            if met.is_some() || gen >= limit{
+                return met;
+            }
+            let jump = conditions.iter().filter(|c|!c.is_monotone()).map(|c|c.safe_jump(self)).min().unwrap_or(u64::MAX);
+            self.step_forward(std::cmp::min(jump, limit - gen));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    // Note this useful idiom: importing names from outer (for mod tests) scope.
+    use super::*;
+    use crate::rle::parse_rle;
+
+    fn tree(rle: &str) -> TreeData{
+        TreeData::from_pattern(&parse_rle(rle).unwrap())
+    }
+    const GLIDER: &str = "x = 3, y = 3\nbo$2bo$3o!\n";
+
+    // first generation found by stepping one at a time
+    fn brute_force(rle: &str, condition: StopCondition, limit: u64) -> Option<u64>{
+        let mut tree = tree(rle);
+        for gen in 0..=limit{
+            if condition.holds(&tree){
+                return Some(gen);
+            }
+            tree.step_forward(1);
+        }
+        None
+    }
+    #[test]
+    fn test_region_and_rect() {
+        for condition in [
+            StopCondition::RegionOccupied(Point{x: 40, y: 41}, 2, 2),
+            StopCondition::LeavesRect(Point{x: -3, y: -5}, 30, 20),
+        ]{
+            let mut glider = tree(GLIDER);
+            assert_eq!(glider.step_until(&[condition], 400), Some(0));
+            let expected = brute_force(GLIDER, condition, 400).unwrap();
+            assert_eq!(glider.get_age(), expected, "{:?}", condition);
+        }
+        // never reached within the limit
+        let mut glider = tree(GLIDER);
+        assert_eq!(glider.step_until(&[StopCondition::RegionOccupied(Point{x: -50, y: 0}, 3, 3)], 300), None);
+        assert_eq!(glider.get_age(), 300);
+    }
+    #[test]
+    fn test_monotone_conditions() {
+        let mut diehard = tree("x = 8, y = 3\n6bo$2o$bo3b3o!\n");
+        assert_eq!(diehard.step_until(&[StopCondition::DiesOut, StopCondition::PopulationAbove(1000)], 1000), Some(0));
+        assert_eq!(diehard.get_age(), 130);
+        // the T-tetromino settles into a traffic light
+        let t = "x = 3, y = 2\n3o$bo!\n";
+        let mut tetromino = tree(t);
+        assert_eq!(tetromino.step_until(&[StopCondition::Periodic(4)], 100), Some(0));
+        assert_eq!(Some(tetromino.get_age()), brute_force(t, StopCondition::Periodic(4), 100));
+        assert!(tetromino.get_age() > 0);
+        // the population threshold comes first and wins
+        let mut tetromino = tree(t);
+        let expected = brute_force(t, StopCondition::PopulationAbove(8), 100).unwrap();
+        assert_eq!(tetromino.step_until(&[StopCondition::Periodic(4), StopCondition::PopulationAbove(8)], 100), Some(1));
+        assert_eq!(tetromino.get_age(), expected);
+    }
+}
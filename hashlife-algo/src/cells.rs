@@ -0,0 +1,117 @@
+/*
+Plaintext (.cells) format, as used by the LifeWiki pattern collection.
+
+Lines starting with `!` are comments, `!Name:` and `!Author:` carry
+metadata, every other line is a row of the pattern with `.` for dead
+and `O` for live cells.
+*/
+
+use crate::parse_error::{ParseError, ParseErrorKind};
+use crate::point::Point;
+use crate::rle::{Pattern, PatternMetadata};
+
+// rows of the pattern relative to its top left live cell, with trailing dead cells left out
+pub(crate) fn grid_rows(points: &[Point], alive: char, dead: char) -> Vec<String>{
+    if points.is_empty(){
+        return Vec::new();
+    }
+    let minx = points.iter().map(|p|p.x).min().unwrap();
+    let miny = points.iter().map(|p|p.y).min().unwrap();
+    let maxy = points.iter().map(|p|p.y).max().unwrap();
+    let mut rows: Vec<Vec<char>> = vec![Vec::new(); (maxy - miny + 1) as usize];
+    for p in points.iter(){
+        let row = &mut rows[(p.y - miny) as usize];
+        let x = (p.x - minx) as usize;
+        if row.len() <= x{
+            row.resize(x + 1, dead);
+        }
+        row[x] = alive;
+    }
+    rows.iter().map(|row|row.iter().collect()).collect()
+}
+// reads one row of `.` and `O` (or `*`) characters, calling `func` on every live cell
+pub(crate) fn parse_grid_line<F>(line: &str, line_no: usize, y: i64, func: &mut F) -> Result<(), ParseError>
+where
+    F: FnMut(Point)
+{
+    for (i, c) in line.chars().enumerate(){
+        match c{
+            '.' => {},
+            'O' | '*' => func(Point{x: i as i64, y}),
+            c => return Err(ParseError::new(line_no, i+1, ParseErrorKind::UnknownState(c))),
+        }
+    }
+    Ok(())
+}
+
+pub fn parse_cells(file_contents: &str) -> Result<Pattern, ParseError>{
+    let mut metadata = PatternMetadata::default();
+    let mut points: Vec<Point> = Vec::new();
+    let mut y = 0;
+    for (i, line) in file_contents.lines().enumerate(){
+        let line = line.trim_end();
+        if let Some(comment) = line.strip_prefix('!'){
+            if let Some(name) = comment.strip_prefix("Name:"){
+                metadata.name = Some(name.trim().to_string());
+            }
+            else if let Some(author) = comment.strip_prefix("Author:"){
+                metadata.author = Some(author.trim().to_string());
+            }
+            else{
+                metadata.comments.push(comment.strip_prefix(' ').unwrap_or(comment).to_string());
+            }
+            continue;
+        }
+        parse_grid_line(line, i+1, y, &mut|p|points.push(p))?;
+        y += 1;
+    }
+    Ok(Pattern{points, metadata})
+}
+
+pub fn write_cells(pattern: &Pattern) -> String{
+    let mut s = String::new();
+    if let Some(name) = &pattern.metadata.name{
+        s.push_str(format!("!Name: {}\n", name).as_str());
+    }
+    if let Some(author) = &pattern.metadata.author{
+        s.push_str(format!("!Author: {}\n", author).as_str());
+    }
+    for comment in pattern.metadata.comments.iter(){
+        s.push('!');
+        s.push_str(comment);
+        s.push('\n');
+    }
+    for row in grid_rows(&pattern.points, 'O', '.'){
+        s.push_str(row.as_str());
+        s.push('\n');
+    }
+    s
+}
+
+#[cfg(test)]
+mod tests {
+    // Note this useful idiom: importing names from outer (for mod tests) scope.
+    use super::*;
+
+    #[test]
+    fn test_cells_round_trip() {
+        let contents = concat!(
+            "!Name: Glider\n",
+            "!Author: Richard K. Guy\n",
+            "!The smallest, most common, and first discovered spaceship.\n",
+            ".O\n",
+            "..O\n",
+            "OOO\n",
+        );
+        let pattern = parse_cells(contents).unwrap();
+        assert_eq!(pattern.metadata.name.as_deref(), Some("Glider"));
+        assert_eq!(pattern.metadata.comments.len(), 1);
+        assert_eq!(pattern.points.len(), 5);
+        assert_eq!(write_cells(&pattern), contents);
+    }
+    #[test]
+    fn test_cells_errors() {
+        let err = parse_cells("!comment\n.O\n.Ox\n").err().unwrap();
+        assert_eq!(err, ParseError::new(3, 3, ParseErrorKind::UnknownState('x')));
+    }
+}
@@ -0,0 +1,299 @@
+/*
+Splitting a stabilised pattern into objects and counting them, the way
+apgsearch does for a soup search.
+
+Cells belong to the same object when they are connected through cells at
+most 2 apart in both x and y, so that cells of different objects can never
+have a neighbour in common. Each object is then run on its own to find
+its period and named by its apgcode.
+*/
+
+use std::collections::HashMap;
+
+use crate::parse_error::{ParseError, ParseErrorKind};
+use crate::point::Point;
+use crate::quadtree::TreeData;
+
+// how many soups are remembered as examples for each object
+const MAX_SAMPLES: usize = 10;
+// name for objects that do not repeat within the allowed period
+pub const UNKNOWN_OBJECT: &str = "zz_UNKNOWN";
+
+fn find_root(parents: &mut [usize], mut i: usize) -> usize{
+    while parents[i] != i{
+        parents[i] = parents[parents[i]];
+        i = parents[i];
+    }
+    i
+}
+
+// groups the points into islands of cells at most 2 apart
+pub fn separate_objects(points: &[Point]) -> Vec<Vec<Point>>{
+    let index: HashMap<Point, usize> = points.iter().enumerate().map(|(i, p)|(*p, i)).collect();
+    let mut parents: Vec<usize> = (0..points.len()).collect();
+    for (i, p) in points.iter().enumerate(){
+        for dy in -2..=2{
+            for dx in -2..=2{
+                if let Some(j) = index.get(&(*p + Point{x: dx, y: dy})){
+                    let (a, b) = (find_root(&mut parents, i), find_root(&mut parents, *j));
+                    parents[a] = b;
+                }
+            }
+        }
+    }
+    let mut groups: HashMap<usize, Vec<Point>> = HashMap::new();
+    for (i, p) in points.iter().enumerate(){
+        let root = find_root(&mut parents, i);
+        groups.entry(root).or_default().push(*p);
+    }
+    let mut objects: Vec<Vec<Point>> = groups.into_values().collect();
+    for object in objects.iter_mut(){
+        object.sort();
+    }
+    objects.sort();
+    objects
+}
+
+pub(crate) fn normalized(points: &[Point]) -> Vec<Point>{
+    let minx = points.iter().map(|p|p.x).min().unwrap_or(0);
+    let miny = points.iter().map(|p|p.y).min().unwrap_or(0);
+    let mut res: Vec<Point> = points.iter().map(|p|*p + Point{x: -minx, y: -miny}).collect();
+    res.sort();
+    res
+}
+
+// apgcode of a single object, running it for at most `max_period` generations
+pub fn classify_object(points: &[Point], max_period: u64) -> String{
+    TreeData::gather_all_points(&points.to_vec()).apgcode(max_period)
+        .unwrap_or_else(||UNKNOWN_OBJECT.to_string())
+}
+
+impl TreeData{
+    pub fn separate_objects(&self) -> Vec<Vec<Point>>{
+        let mut objects: Vec<Vec<Point>> = self.components(2).iter().map(|c|{
+            let mut object = c.tree.dump_all_points();
+            object.sort();
+            object
+        }).collect();
+        objects.sort();
+        objects
+    }
+}
+
+// header fields of a Catagolue haul
+pub struct HaulHeader{
+    pub version: String,
+    pub root: String,
+    pub rule: String,
+    pub symmetry: String,
+}
+
+#[derive(Default)]
+pub struct Census{
+    counts: HashMap<String, u64>,
+    samples: HashMap<String, Vec<String>>,
+    num_soups: u64,
+    // apgcodes of objects already classified, by their normalised cells
+    known: HashMap<Vec<Point>, String>,
+}
+impl Census{
+    pub fn new() -> Census{
+        Census::default()
+    }
+    pub fn num_soups(&self) -> u64{
+        self.num_soups
+    }
+    pub fn num_objects(&self) -> u64{
+        self.counts.values().sum()
+    }
+    pub fn count(&self, apgcode: &str) -> u64{
+        self.counts.get(apgcode).copied().unwrap_or(0)
+    }
+    pub fn samples(&self, apgcode: &str) -> &[String]{
+        self.samples.get(apgcode).map(|s|&s[..]).unwrap_or(&[])
+    }
+    pub fn add_object(&mut self, apgcode: &str, soup_id: Option<&str>){
+        *self.counts.entry(apgcode.to_string()).or_insert(0) += 1;
+        if let Some(soup_id) = soup_id{
+            let samples = self.samples.entry(apgcode.to_string()).or_default();
+            if samples.len() < MAX_SAMPLES && !samples.iter().any(|s|s == soup_id){
+                samples.push(soup_id.to_string());
+            }
+        }
+    }
+    // counts every object of an already stabilised soup, returning their apgcodes
+    pub fn add_soup(&mut self, tree: &TreeData, soup_id: &str, max_period: u64) -> Vec<String>{
+        let mut codes = Vec::new();
+        for object in tree.separate_objects(){
+            let key = normalized(&object);
+            let code = match self.known.get(&key){
+                Some(code) => code.clone(),
+                None => {
+                    let code = classify_object(&object, max_period);
+                    self.known.insert(key, code.clone());
+                    code
+                }
+            };
+            self.add_object(&code, Some(soup_id));
+            codes.push(code);
+        }
+        self.num_soups += 1;
+        codes
+    }
+    pub fn merge(&mut self, other: &Census){
+        for (code, count) in other.counts.iter(){
+            *self.counts.entry(code.clone()).or_insert(0) += count;
+        }
+        for (code, samples) in other.samples.iter(){
+            for soup_id in samples.iter(){
+                let mine = self.samples.entry(code.clone()).or_default();
+                if mine.len() < MAX_SAMPLES && !mine.contains(soup_id){
+                    mine.push(soup_id.clone());
+                }
+            }
+        }
+        self.num_soups += other.num_soups;
+    }
+    // objects with their counts, most common first
+    pub fn table(&self) -> Vec<(String, u64)>{
+        let mut table: Vec<(String, u64)> = self.counts.iter().map(|(c, n)|(c.clone(), *n)).collect();
+        table.sort_by(|a, b|b.1.cmp(&a.1).then_with(||a.0.cmp(&b.0)));
+        table
+    }
+    pub fn write_haul(&self, header: &HaulHeader) -> String{
+        let mut s = String::new();
+        s.push_str(format!("@VERSION {}\n", header.version).as_str());
+        s.push_str(format!("@ROOT {}\n", header.root).as_str());
+        s.push_str(format!("@RULE {}\n", header.rule).as_str());
+        s.push_str(format!("@SYMMETRY {}\n", header.symmetry).as_str());
+        s.push_str(format!("@NUM_SOUPS {}\n", self.num_soups).as_str());
+        s.push_str(format!("@NUM_OBJECTS {}\n", self.num_objects()).as_str());
+        s.push_str("\n@CENSUS TABLE\n");
+        let table = self.table();
+        for (code, count) in table.iter(){
+            s.push_str(format!("{} {}\n", code, count).as_str());
+        }
+        s.push_str("\n@SAMPLE_SOUPIDS\n");
+        for (code, _) in table.iter(){
+            let samples = self.samples(code);
+            if !samples.is_empty(){
+                s.push_str(format!("{} {}\n", code, samples.join(" ")).as_str());
+            }
+        }
+        s
+    }
+    // reads back a haul written by write_haul, e.g. to resume a search
+    pub fn read_haul(text: &str) -> Result<(HaulHeader, Census), ParseError>{
+        let mut header = HaulHeader{version: String::new(), root: String::new(), rule: String::new(), symmetry: String::new()};
+        let mut census = Census::new();
+        let mut section = "";
+        for (i, line) in text.lines().enumerate(){
+            let line = line.trim();
+            if line.is_empty(){
+                continue;
+            }
+            if let Some(field) = line.strip_prefix('@'){
+                let (name, value) = field.split_once(' ').unwrap_or((field, ""));
+                match name{
+                    "VERSION" => header.version = value.to_string(),
+                    "ROOT" => header.root = value.to_string(),
+                    "RULE" => header.rule = value.to_string(),
+                    "SYMMETRY" => header.symmetry = value.to_string(),
+                    "NUM_SOUPS" => census.num_soups = value.parse().map_err(|_|{
+                        ParseError::new(i + 1, 1, ParseErrorKind::InvalidMetadata(line.to_string()))
+                    })?,
+                    _ => {},
+                }
+                section = name;
+                continue;
+            }
+            let mut words = line.split_whitespace();
+            let code = words.next().unwrap();
+            match section{
+                "CENSUS" => {
+                    let count = words.next().and_then(|n|n.parse::<u64>().ok()).ok_or_else(||{
+                        ParseError::new(i + 1, code.len() + 2, ParseErrorKind::InvalidMetadata(line.to_string()))
+                    })?;
+                    census.counts.insert(code.to_string(), count);
+                },
+                "SAMPLE_SOUPIDS" => {
+                    census.samples.insert(code.to_string(), words.map(|w|w.to_string()).collect());
+                },
+                _ => return Err(ParseError::new(i + 1, 1, ParseErrorKind::InvalidMetadata(line.to_string()))),
+            }
+        }
+        Ok((header, census))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    // Note this useful idiom: importing names from outer (for mod tests) scope.
+    use super::*;
+    use crate::rle::parse_rle;
+
+    fn code(rle: &str) -> String{
+        classify_object(&parse_rle(rle).unwrap().points, 16)
+    }
+    #[test]
+    fn test_classify_object() {
+        assert_eq!(code("x = 2, y = 2\n2o$2o!\n"), "xs4_33");
+        assert_eq!(code("x = 4, y = 3\nb2o$o2bo$b2o!\n"), "xs6_696");
+        assert_eq!(code("x = 3, y = 1\n3o!\n"), "xp2_7");
+        assert_eq!(code("x = 4, y = 2\nb3o$3o!\n"), "xp2_7e");
+        assert_eq!(code("x = 4, y = 4\n2o$2o$2b2o$2b2o!\n"), "xp2_318c");
+        assert_eq!(code("x = 3, y = 3\nbo$2bo$3o!\n"), "xq4_153");
+        assert_eq!(code("x = 5, y = 4\nbo2bo$o4b$o3bo$4o!\n"), "xq4_6frc");
+        assert_eq!(code("x = 2, y = 1\n2o!\n"), UNKNOWN_OBJECT);
+    }
+    #[test]
+    fn test_separate_objects() {
+        // a block, a blinker 2 cells away from a tub making one object, and a lone cell
+        let rle = "x = 18, y = 15\n2o$2o5$8b3o$12bo$11bobo$12bo5$17bo!\n";
+        let objects = separate_objects(&parse_rle(rle).unwrap().points);
+        assert_eq!(objects.iter().map(|o|o.len()).collect::<Vec<usize>>(), vec![4, 7, 1]);
+    }
+    #[test]
+    fn test_census() {
+        let rle = "x = 23, y = 9\n2o10b2o$2o10b2o5$8b3o10bo$22bo$20b3o!\n";
+        let tree = TreeData::from_pattern(&parse_rle(rle).unwrap());
+        let mut census = Census::new();
+        census.add_soup(&tree, "seed1", 16);
+        let mut other = Census::new();
+        other.add_soup(&TreeData::from_pattern(&parse_rle("x = 2, y = 2\n2o$2o!\n").unwrap()), "seed2", 16);
+        census.merge(&other);
+        assert_eq!(census.table(), vec![
+            ("xs4_33".to_string(), 3),
+            ("xp2_7".to_string(), 1),
+            ("xq4_153".to_string(), 1),
+        ]);
+        let header = HaulHeader{
+            version: "hashlife-rust".to_string(),
+            root: "test".to_string(),
+            rule: "b3s23".to_string(),
+            symmetry: "C1".to_string(),
+        };
+        assert_eq!(census.write_haul(&header), concat!(
+            "@VERSION hashlife-rust\n",
+            "@ROOT test\n",
+            "@RULE b3s23\n",
+            "@SYMMETRY C1\n",
+            "@NUM_SOUPS 2\n",
+            "@NUM_OBJECTS 5\n",
+            "\n@CENSUS TABLE\n",
+            "xs4_33 3\n",
+            "xp2_7 1\n",
+            "xq4_153 1\n",
+            "\n@SAMPLE_SOUPIDS\n",
+            "xs4_33 seed1 seed2\n",
+            "xp2_7 seed1\n",
+            "xq4_153 seed1\n",
+        ));
+        let (read_header, read) = Census::read_haul(&census.write_haul(&header)).unwrap();
+        assert_eq!(read_header.root, "test");
+        assert_eq!(read.num_soups(), 2);
+        assert_eq!(read.table(), census.table());
+        assert_eq!(read.samples("xs4_33"), census.samples("xs4_33"));
+        assert!(Census::read_haul("@CENSUS TABLE\nxs4_33 many\n").is_err());
+    }
+}
@@ -0,0 +1,305 @@
+/*
+Enumeration of the collisions of a spaceship with a target pattern, for
+glider synthesis.
+
+A spaceship moving by `d` every `p` generations is sent down every lane
+that passes close enough to the target to touch it. Lanes are numbered
+by their offset across the direction of travel. Starting the spaceship in
+a later phase, or further back along its lane by `d`, only delays the
+collision, which makes no difference unless the target oscillates. The
+timings within a lane are therefore the cell steps along the lane that
+are not whole multiples of `d`, each with a delay of every generation of
+the target's period.
+
+Each collision is the union of the target and the placed spaceship, run
+to stabilisation with hashlife, left to settle and split into objects.
+Collisions that end with the target and spaceship exactly as they would
+have been without each other are dropped.
+*/
+
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use crate::census::Census;
+use crate::compare::Equivalence;
+use crate::point::Point;
+use crate::quadtree::TreeData;
+
+// generations run past stabilisation so that escaping gliders get clear of the debris
+const SETTLE_GENERATIONS: u64 = 256;
+// cells at most this far apart in both x and y can have a neighbour in common
+const TOUCH_DISTANCE: i64 = 2;
+// usual names of some common objects, used in place of their apgcodes
+const COMMON_NAMES: [(&str, &str); 10] = [
+    ("xs4_33", "block"), ("xp2_7", "blinker"), ("xs6_696", "beehive"), ("xs5_253", "boat"),
+    ("xs6_356", "ship"), ("xs7_2596", "loaf"), ("xs4_252", "tub"), ("xs8_6996", "pond"),
+    ("xq4_153", "glider"), ("xq4_6frc", "lwss"),
+];
+pub const CLEAN_KILL: &str = "clean kill";
+pub const UNSTABLE: &str = "unstable";
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct CollisionOptions{
+    pub max_generations: u64,
+    pub max_period: u64,
+    pub max_nodes: usize,
+    pub threads: usize,
+    // only the lanes in this inclusive range, instead of every lane that can touch the target
+    pub lanes: Option<(i64, i64)>,
+}
+impl Default for CollisionOptions{
+    fn default() -> CollisionOptions{
+        CollisionOptions{max_generations: 10000, max_period: 60, max_nodes: 4000000, threads: 1, lanes: None}
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Collision{
+    // offset across the direction of travel
+    pub lane: i64,
+    pub timing: u64,
+    // the spaceship is run this many generations and then moved by `offset`
+    pub phase: u64,
+    pub offset: Point,
+    // objects left over, e.g. "block + glider", or CLEAN_KILL or UNSTABLE
+    pub outcome: String,
+    // generation the collision stabilised at, None if it did not
+    pub generation: Option<u64>,
+}
+
+// names the objects left over, counting repeats, e.g. "2 block + glider"
+fn describe(codes: &[String]) -> String{
+    if codes.is_empty(){
+        return CLEAN_KILL.to_string();
+    }
+    let mut counts: HashMap<&str, usize> = HashMap::new();
+    for code in codes.iter(){
+        let name = COMMON_NAMES.iter().find(|(c, _)|c == code).map_or(code.as_str(), |(_, name)|*name);
+        *counts.entry(name).or_insert(0) += 1;
+    }
+    let mut names: Vec<(&str, usize)> = counts.into_iter().collect();
+    names.sort();
+    names.iter().map(|(name, n)|if *n == 1 {name.to_string()} else {format!("{} {}", n, name)}).collect::<Vec<_>>().join(" + ")
+}
+
+// collisions grouped by outcome, most common outcome first
+pub fn group_by_outcome(collisions: &[Collision]) -> Vec<(String, Vec<Collision>)>{
+    let mut groups: HashMap<String, Vec<Collision>> = HashMap::new();
+    for collision in collisions.iter(){
+        groups.entry(collision.outcome.clone()).or_default().push(collision.clone());
+    }
+    let mut res: Vec<(String, Vec<Collision>)> = groups.into_iter().collect();
+    res.sort_by(|a, b|b.1.len().cmp(&a.1.len()).then_with(||a.0.cmp(&b.0)));
+    res
+}
+
+// (g, s, t) with a*s + b*t = g, the gcd of a and b
+fn ext_gcd(a: i64, b: i64) -> (i64, i64, i64){
+    if b == 0{
+        return if a < 0 {(-a, -1, 0)} else {(a, 1, 0)};
+    }
+    let (g, s, t) = ext_gcd(b, a % b);
+    (g, t, s - (a / b)*t)
+}
+
+fn dot(a: Point, b: Point) -> i64{
+    a.x*b.x + a.y*b.y
+}
+
+fn scaled(p: Point, k: i64) -> Point{
+    Point{x: p.x*k, y: p.y*k}
+}
+
+// lane of a cell for a spaceship moving along `along`
+fn lane_of(along: Point, p: Point) -> i64{
+    along.x*p.y - along.y*p.x
+}
+
+fn lane_range<'a>(cells: impl Iterator<Item = &'a Point>, along: Point) -> (i64, i64){
+    cells.map(|p|lane_of(along, *p)).fold((i64::MAX, i64::MIN), |(lo, hi), l|(lo.min(l), hi.max(l)))
+}
+
+// the cells of every phase of a pattern
+fn phase_cells(tree: &TreeData, period: u64) -> Vec<Vec<Point>>{
+    let mut tree = tree.pruned_tree();
+    let mut res = vec![tree.dump_all_points()];
+    for _ in 1..period{
+        tree.step_forward(1);
+        res.push(tree.dump_all_points());
+    }
+    res
+}
+
+struct Placement{
+    lane: i64,
+    timing: u64,
+    phase: u64,
+    offset: Point,
+}
+
+impl TreeData{
+    // this pattern together with `projectile`, run `phase` generations and moved by `offset`
+    pub fn with_projectile(&self, projectile: &TreeData, phase: u64, offset: Point) -> TreeData{
+        let mut ship = projectile.pruned_tree();
+        if phase > 0{
+            ship.step_forward(phase);
+        }
+        self.union(&ship, offset)
+    }
+    // every placement of the spaceship that can touch this pattern, in lane and timing order
+    fn placements(&self, projectile: &TreeData, max_period: u64) -> Option<Vec<Placement>>{
+        let ship = projectile.pruned_tree().find_period(max_period).filter(|p|p.is_spaceship())?;
+        let target_period = self.pruned_tree().find_period(max_period)
+            .filter(|p|!p.is_spaceship())
+            .map_or(1, |p|p.period);
+        let (period, d) = (ship.period, ship.displacement);
+        let (g, s, t) = ext_gcd(d.x, d.y);
+        // one cell along the lane and one lane across
+        let along = Point{x: d.x / g, y: d.y / g};
+        let across = Point{x: -t, y: s};
+        // moving back by this many periods of the spaceship keeps the target in the same phase
+        let periods = target_period / ext_gcd(period as i64, target_period as i64).0 as u64;
+        let ship_cells = phase_cells(projectile, std::cmp::max(period, target_period));
+        let target_cells: Vec<Point> = phase_cells(self, target_period).into_iter().flatten().collect();
+        let (ship_lo, ship_hi) = lane_range(ship_cells.iter().flatten(), along);
+        let (target_lo, target_hi) = lane_range(target_cells.iter(), along);
+        let margin = TOUCH_DISTANCE*(along.x.abs() + along.y.abs());
+        let target_back = target_cells.iter().map(|p|dot(d, *p)).min()?;
+        // the spaceship starts clear of the target by more than it can reach in a generation
+        let clearance = (TOUCH_DISTANCE + 1)*(d.x.abs() + d.y.abs());
+        let mut res = Vec::new();
+        for lane in (target_lo - margin - ship_hi)..=(target_hi + margin - ship_lo){
+            for timing in 0..g as u64 * target_period{
+                let phase = timing % target_period;
+                let start = scaled(across, lane) + scaled(along, (timing / target_period) as i64);
+                let front = ship_cells[phase as usize].iter().map(|p|dot(d, *p)).max()?;
+                // moved back by whole `periods` so the timing stays the same
+                let step = periods as i64 * dot(d, d);
+                let back = (dot(d, start) + front + clearance - target_back).div_euclid(step) + 1;
+                let offset = start + scaled(d, -(back * periods as i64));
+                res.push(Placement{lane, timing, phase, offset});
+            }
+        }
+        Some(res)
+    }
+    fn run_collision(&self, projectile: &TreeData, placement: &Placement, options: &CollisionOptions, census: &mut Census) -> Option<Collision>{
+        let start = self.with_projectile(projectile, placement.phase, placement.offset);
+        let stabilisation = start.find_stabilisation_within(options.max_generations, options.max_period, options.max_nodes);
+        let outcome = match stabilisation{
+            Some(stabilisation) => {
+                let gens = stabilisation.generation + SETTLE_GENERATIONS;
+                let mut end = start;
+                end.step_forward(gens);
+                // what the two would have become without meeting
+                let mut target = self.pruned_tree();
+                target.step_forward(gens);
+                if end.same_pattern(&target.with_projectile(projectile, placement.phase + gens, placement.offset), Equivalence::Exact){
+                    return None;
+                }
+                describe(&census.add_soup(&end, "", options.max_period))
+            },
+            None => UNSTABLE.to_string(),
+        };
+        Some(Collision{
+            lane: placement.lane,
+            timing: placement.timing,
+            phase: placement.phase,
+            offset: placement.offset,
+            outcome,
+            generation: stabilisation.map(|s|s.generation),
+        })
+    }
+    // runs the spaceship `projectile` into this pattern in every lane and timing where they meet,
+    // on `options.threads` threads. None if `projectile` is not a spaceship.
+    pub fn collisions(&self, projectile: &TreeData, options: &CollisionOptions) -> Option<Vec<Collision>>{
+        let placements: Vec<Placement> = self.placements(projectile, options.max_period)?.into_iter()
+            .filter(|p|options.lanes.is_none_or(|(lo, hi)|lo <= p.lane && p.lane <= hi))
+            .collect();
+        // trees are not shared between threads, so each one rebuilds its own from the cells
+        let (target_cells, ship_cells) = (self.dump_all_points(), projectile.dump_all_points());
+        let next = AtomicUsize::new(0);
+        let work = ||{
+            let target = TreeData::gather_all_points(&target_cells);
+            let ship = TreeData::gather_all_points(&ship_cells);
+            let mut census = Census::new();
+            let mut done = Vec::new();
+            loop{
+                let i = next.fetch_add(1, Ordering::Relaxed);
+                if i >= placements.len(){
+                    return done;
+                }
+                if let Some(collision) = target.run_collision(&ship, &placements[i], options, &mut census){
+                    done.push((i, collision));
+                }
+            }
+        };
+        let mut res: Vec<(usize, Collision)> = if options.threads <= 1{
+            work()
+        }
+        else{
+            std::thread::scope(|scope|{
+                let workers: Vec<_> = (0..options.threads).map(|_|scope.spawn(work)).collect();
+                workers.into_iter().flat_map(|w|w.join().unwrap()).collect()
+            })
+        };
+        res.sort_by_key(|(i, _)|*i);
+        Some(res.into_iter().map(|(_, collision)|collision).collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    // Note this useful idiom: importing names from outer (for mod tests) scope.
+    use super::*;
+    use crate::rle::parse_rle;
+
+    fn tree(rle: &str) -> TreeData{
+        TreeData::from_pattern(&parse_rle(rle).unwrap())
+    }
+    const GLIDER: &str = "x = 3, y = 3\nbo$2bo$3o!\n";
+    const BLOCK: &str = "x = 2, y = 2\n2o$2o!\n";
+
+    #[test]
+    fn test_common_names() {
+        for (rle, name) in [(BLOCK, "block"), ("x = 3, y = 1\n3o!\n", "blinker"), ("x = 4, y = 3\nb2o$o2bo$b2o!\n", "beehive"),
+            ("x = 3, y = 3\n2o$obo$bo!\n", "boat"), (GLIDER, "glider"), ("x = 5, y = 4\nbo2bo$o$o3bo$4o!\n", "lwss")]{
+            let code = tree(rle).apgcode(10).unwrap();
+            assert_eq!(describe(&[code]), name);
+        }
+        let codes: Vec<String> = ["xq4_153", "xs4_33", "xs4_33"].iter().map(|s|s.to_string()).collect();
+        assert_eq!(describe(&codes), "2 block + glider");
+        assert_eq!(describe(&[]), CLEAN_KILL);
+    }
+    #[test]
+    fn test_placements() {
+        let (block, glider) = (tree(BLOCK), tree(GLIDER));
+        let placements = block.placements(&glider, 10).unwrap();
+        // one timing in every lane, starting clear of the block
+        assert!(placements.iter().all(|p|p.timing == 0 && p.phase == 0));
+        assert!(placements.len() >= 8);
+        for placement in placements.iter(){
+            let start = block.with_projectile(&glider, placement.phase, placement.offset);
+            assert_eq!(start.separate_objects().len(), 2);
+        }
+        // against a blinker the glider can come a generation later
+        let timings: Vec<u64> = tree("x = 3, y = 1\n3o!\n").placements(&glider, 10).unwrap().iter().map(|p|p.timing).collect();
+        assert_eq!(timings[..4], [0, 1, 0, 1]);
+        assert!(glider.placements(&block, 10).is_none());
+    }
+    #[test]
+    fn test_glider_block() {
+        let (block, glider) = (tree(BLOCK), tree(GLIDER));
+        // lanes that settle quickly: hitting the block head on, and pulling it
+        let options = CollisionOptions{max_generations: 2000, threads: 2, lanes: Some((-3, 2)), ..CollisionOptions::default()};
+        let mut collisions = block.collisions(&glider, &options).unwrap();
+        collisions.extend(block.collisions(&glider, &CollisionOptions{lanes: Some((5, 5)), ..options}).unwrap());
+        let groups = group_by_outcome(&collisions);
+        let outcomes: Vec<(&str, usize)> = groups.iter().map(|(o, c)|(o.as_str(), c.len())).collect();
+        assert_eq!(outcomes, vec![(CLEAN_KILL, 6), ("block", 1)]);
+        assert_eq!(groups[1].1[0].lane, 5);
+        // each collision can be set up again from its placement
+        let collision = &groups[0].1[0];
+        let start = block.with_projectile(&glider, collision.phase, collision.offset);
+        assert_eq!(start.find_stabilisation(2000, 60).map(|s|s.generation), collision.generation);
+    }
+}
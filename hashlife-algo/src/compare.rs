@@ -0,0 +1,147 @@
+/*
+Comparing patterns regardless of where they sit in their trees.
+
+Each pattern is reframed into the smallest node with its bounding box at
+the top left corner, so two trees with the same cells give the same node
+key whatever their offsets and depths. Node keys are hashes of the
+children's keys down to the raw leaves, so they are the same in every
+tree and do not depend on the order nodes were created in.
+
+The fingerprint is meant to be stored, so it does not use the node keys,
+which change with the node layout and hash function. It is the SHA-256 of
+the cells of the smallest of the 8 orientations, each moved so that its
+bounding box starts at 0,0 and listed row by row as "x,y;", orientations
+being compared by those lists.
+*/
+
+use crate::point::Point;
+use crate::quadtree::TreeData;
+use crate::sha256::sha256;
+use crate::transform::Transform;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Equivalence{
+    // the same cells at the same coordinates
+    Exact,
+    // the same cells up to translation
+    Translation,
+    // the same cells up to translation, rotation and reflection
+    Symmetry,
+}
+
+// the normalised node of a pattern and the top left corner of its bounding box
+type Shape = Option<(u128, u64, Point)>;
+
+fn shapes_match(a: &Shape, b: &Shape, equivalence: Equivalence) -> bool{
+    match (a, b){
+        (Some((key_a, depth_a, corner_a)), Some((key_b, depth_b, corner_b))) => {
+            key_a == key_b && depth_a == depth_b && (equivalence != Equivalence::Exact || corner_a == corner_b)
+        },
+        (None, None) => true,
+        _ => false,
+    }
+}
+
+impl TreeData{
+    fn shape(&self, transform: Transform) -> Shape{
+        let mut tree = self.pruned_tree();
+        tree.transform(transform);
+        tree.normalized_root()
+    }
+    // the shapes `other` is compared against
+    fn shapes(&self, equivalence: Equivalence) -> Vec<Shape>{
+        match equivalence{
+            Equivalence::Symmetry => Transform::ALL.iter().map(|t|self.shape(*t)).collect(),
+            _ => vec![self.shape(Transform::Identity)],
+        }
+    }
+    pub fn same_pattern(&self, other: &TreeData, equivalence: Equivalence) -> bool{
+        let shape = other.shape(Transform::Identity);
+        self.shapes(equivalence).iter().any(|s|shapes_match(s, &shape, equivalence))
+    }
+    // number of generations `other` has to be run on to match this pattern,
+    // trying at most `max_generations`
+    pub fn matching_phase(&self, other: &TreeData, equivalence: Equivalence, max_generations: u64) -> Option<u64>{
+        let shapes = self.shapes(equivalence);
+        let mut other = other.pruned_tree();
+        for gen in 0..=max_generations{
+            if gen != 0{
+                other.step_forward(1);
+            }
+            let shape = other.shape(Transform::Identity);
+            if shapes.iter().any(|s|shapes_match(s, &shape, equivalence)){
+                return Some(gen);
+            }
+        }
+        None
+    }
+    // identifies the pattern up to translation, rotation and reflection
+    pub fn fingerprint(&self) -> String{
+        let cells = Transform::ALL.iter().map(|t|{
+            let mut tree = self.pruned_tree();
+            tree.transform(*t);
+            let mut points = tree.dump_all_points();
+            let corner = Point{x: points.iter().map(|p|p.x).min().unwrap_or(0), y: points.iter().map(|p|p.y).min().unwrap_or(0)};
+            let mut normalised: Vec<Point> = points.drain(..).map(|p|p + corner.neg()).collect();
+            normalised.sort();
+            normalised
+        }).min().unwrap();
+        let text: String = cells.iter().map(|p|format!("{},{};", p.x, p.y)).collect();
+        sha256(text.as_bytes()).iter().map(|b|format!("{:02x}", b)).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    // Note this useful idiom: importing names from outer (for mod tests) scope.
+    use super::*;
+    use crate::rle::parse_rle;
+
+    fn glider() -> TreeData{
+        TreeData::from_pattern(&parse_rle("x = 3, y = 3\nbo$2bo$3o!\n").unwrap())
+    }
+    #[test]
+    fn test_same_pattern() {
+        let a = glider();
+        let mut moved = glider();
+        moved.translate(Point{x: 500, y: -37});
+        // the same cells in a deeper tree
+        let mut deeper = glider();
+        deeper.increase_depth();
+        deeper.increase_depth();
+        let mut turned = glider();
+        turned.transform(Transform::Rotate90);
+        assert!(a.same_pattern(&deeper, Equivalence::Exact));
+        assert!(!a.same_pattern(&moved, Equivalence::Exact));
+        assert!(a.same_pattern(&moved, Equivalence::Translation));
+        assert!(!a.same_pattern(&turned, Equivalence::Translation));
+        assert!(a.same_pattern(&turned, Equivalence::Symmetry));
+        assert!(TreeData::new().same_pattern(&TreeData::new(), Equivalence::Exact));
+        assert!(!a.same_pattern(&TreeData::new(), Equivalence::Symmetry));
+    }
+    #[test]
+    fn test_fingerprint() {
+        let a = glider();
+        for transform in Transform::ALL{
+            let mut b = glider();
+            b.transform(transform);
+            b.translate(Point{x: -3, y: 90});
+            assert_eq!(b.fingerprint(), a.fingerprint());
+        }
+        let block = TreeData::from_pattern(&parse_rle("x = 2, y = 2\n2o$2o!\n").unwrap());
+        assert_ne!(block.fingerprint(), a.fingerprint());
+        // pinned, since fingerprints are stored
+        assert_eq!(a.fingerprint(), "6715de24c59b4bedc6a321974d4973c1fb2c31e4250bf107fd218e43fb422ca4");
+    }
+    #[test]
+    fn test_matching_phase() {
+        let mut later = glider();
+        later.step_forward(2);
+        assert_eq!(later.matching_phase(&glider(), Equivalence::Translation, 10), Some(2));
+        // the glider's second phase is a reflection of its first
+        assert_eq!(later.matching_phase(&glider(), Equivalence::Symmetry, 10), Some(0));
+        assert_eq!(later.matching_phase(&glider(), Equivalence::Exact, 10), Some(2));
+        later.translate(Point{x: 1, y: 0});
+        assert_eq!(later.matching_phase(&glider(), Equivalence::Exact, 10), None);
+    }
+}
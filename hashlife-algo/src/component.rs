@@ -0,0 +1,237 @@
+/*
+Splitting a universe into its connected components, cells being connected
+when they are at most `distance` apart in both x and y.
+
+Only the occupied 8x8 leaves are visited, empty quadrants being skipped
+by their live cell count, so widely separated objects cost nothing for
+the space between them. Each leaf is split into pieces by growing a seed
+cell inside its u64 until it stops changing, and pieces are joined across
+neighbouring leaves by growing them the same way into the neighbour's
+cells. The components are then built back into trees leaf by leaf.
+*/
+
+use std::collections::HashMap;
+
+use crate::frame::node_size;
+use crate::point::Point;
+use crate::quadtree::TreeData;
+
+// furthest a cell can reach into a neighbouring leaf
+const MAX_DISTANCE: i64 = 8;
+
+pub struct Component{
+    pub tree: TreeData,
+    // inclusive corners
+    pub bounding_box: (Point, Point),
+    pub population: u64,
+}
+
+// cells of the leaf `(dx, dy)` leaves away that are within `distance` in both x and y of a cell of `mask`
+fn reach(mask: u64, distance: i64, dx: i64, dy: i64) -> u64{
+    let mut res = 0;
+    for y in 0..8{
+        // the row in the frame of `mask`
+        let row_y = y + 8*dy;
+        let (lo, hi) = (std::cmp::max(row_y - distance, 0), std::cmp::min(row_y + distance, 7));
+        // rows of `mask` close enough, with x = -8 at bit 0
+        let mut rows: u64 = 0;
+        for sy in lo..=hi{
+            rows |= ((mask >> (8*sy)) & 0xff) << 8;
+        }
+        if rows == 0{
+            continue;
+        }
+        let mut grown = rows;
+        for k in 1..=distance{
+            grown |= (rows << k) | (rows >> k);
+        }
+        res |= ((grown >> (8 + 8*dx)) & 0xff) << (8*y);
+    }
+    res
+}
+
+// splits the cells of a leaf into pieces connected within the leaf
+fn leaf_pieces(leaf: u64, distance: i64) -> Vec<u64>{
+    let mut res = Vec::new();
+    let mut remaining = leaf;
+    while remaining != 0{
+        let mut piece = remaining & remaining.wrapping_neg();
+        loop{
+            let grown = reach(piece, distance, 0, 0) & leaf;
+            if grown == piece{
+                break;
+            }
+            piece = grown;
+        }
+        remaining &= !piece;
+        res.push(piece);
+    }
+    res
+}
+
+fn find_root(parents: &mut [usize], mut i: usize) -> usize{
+    while parents[i] != i{
+        parents[i] = parents[parents[i]];
+        i = parents[i];
+    }
+    i
+}
+
+fn leaf_bounding_box(pos: Point, leaf: u64) -> (Point, Point){
+    let rows: Vec<i64> = (0..8).filter(|y|(leaf >> (8*y)) & 0xff != 0).collect();
+    let columns = (0..8).map(|y|(leaf >> (8*y)) & 0xff).fold(0, |a, b|a | b);
+    let (min_x, max_x) = (columns.trailing_zeros() as i64, 63 - columns.leading_zeros() as i64);
+    (pos + Point{x: min_x, y: rows[0]}, pos + Point{x: max_x, y: *rows.last().unwrap()})
+}
+
+impl TreeData{
+    // occupied leaves by the position of their top left corner
+    fn collect_leaves(&self, key: u128, depth: u64, pos: Point, leaves: &mut HashMap<Point, u64>){
+        if self.is_empty_node(key, depth){
+            return;
+        }
+        if depth == 0{
+            leaves.insert(pos, key as u64);
+            return;
+        }
+        let half = node_size(depth - 1);
+        for (i, child) in self.children(key).iter().enumerate(){
+            self.collect_leaves(*child, depth - 1, pos + Point{x: (i as i64 % 2)*half, y: (i as i64 / 2)*half}, leaves);
+        }
+    }
+    // the connected components, cells at most `distance` apart in both x and y being connected,
+    // ordered by the top left corner of their bounding box
+    pub fn components(&self, distance: i64) -> Vec<Component>{
+        assert!((1..=MAX_DISTANCE).contains(&distance));
+        let mut leaves = HashMap::new();
+        self.collect_leaves(self.root, self.depth, self.offset, &mut leaves);
+        let mut pieces: Vec<(Point, u64)> = Vec::new();
+        let mut leaf_piece_ids: HashMap<Point, Vec<usize>> = HashMap::new();
+        for (pos, leaf) in leaves.iter(){
+            for piece in leaf_pieces(*leaf, distance){
+                leaf_piece_ids.entry(*pos).or_default().push(pieces.len());
+                pieces.push((*pos, piece));
+            }
+        }
+        let mut parents: Vec<usize> = (0..pieces.len()).collect();
+        for (i, (pos, piece)) in pieces.iter().enumerate(){
+            for (dx, dy) in [(-1, -1), (0, -1), (1, -1), (-1, 0), (1, 0), (-1, 1), (0, 1), (1, 1)]{
+                let Some(ids) = leaf_piece_ids.get(&(*pos + Point{x: 8*dx, y: 8*dy})) else {continue};
+                let reached = reach(*piece, distance, dx, dy);
+                for j in ids.iter(){
+                    if reached & pieces[*j].1 != 0{
+                        let (a, b) = (find_root(&mut parents, i), find_root(&mut parents, *j));
+                        parents[a] = b;
+                    }
+                }
+            }
+        }
+        let mut groups: HashMap<usize, HashMap<Point, u64>> = HashMap::new();
+        for (i, (pos, piece)) in pieces.iter().enumerate(){
+            *groups.entry(find_root(&mut parents, i)).or_default().entry(*pos).or_insert(0) |= piece;
+        }
+        let mut res: Vec<Component> = groups.into_values().map(|group|{
+            let corner = Point{x: group.keys().map(|p|p.x).min().unwrap(), y: group.keys().map(|p|p.y).min().unwrap()};
+            let mut min = Point{x: i64::MAX, y: i64::MAX};
+            let mut max = Point{x: i64::MIN, y: i64::MIN};
+            let mut population = 0;
+            for (pos, leaf) in group.iter(){
+                let (lo, hi) = leaf_bounding_box(*pos, *leaf);
+                min = Point{x: min.x.min(lo.x), y: min.y.min(lo.y)};
+                max = Point{x: max.x.max(hi.x), y: max.y.max(hi.y)};
+                population += leaf.count_ones() as u64;
+            }
+            let leaves = group.into_iter().map(|(pos, leaf)|(Point{x: (pos.x - corner.x) / 8, y: (pos.y - corner.y) / 8}, leaf as u128)).collect();
+            let mut tree = TreeData::gather_leaves(leaves, corner);
+            tree.age = self.age;
+            Component{tree, bounding_box: (min, max), population}
+        }).collect();
+        res.sort_by_key(|c|(c.bounding_box.0.y, c.bounding_box.0.x, c.bounding_box.1.y, c.bounding_box.1.x));
+        res
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    // Note this useful idiom: importing names from outer (for mod tests) scope.
+    use super::*;
+    use crate::census::separate_objects;
+    use std::collections::HashSet;
+
+    // connected components by brute force, for cells at most `distance` apart
+    fn brute_force(points: &[Point], distance: i64) -> Vec<Vec<Point>>{
+        let mut left: HashSet<Point> = points.iter().cloned().collect();
+        let mut res = Vec::new();
+        while let Some(seed) = left.iter().next().cloned(){
+            left.remove(&seed);
+            let mut component = vec![seed];
+            let mut i = 0;
+            while i < component.len(){
+                let p = component[i];
+                for dy in -distance..=distance{
+                    for dx in -distance..=distance{
+                        if left.remove(&(p + Point{x: dx, y: dy})){
+                            component.push(p + Point{x: dx, y: dy});
+                        }
+                    }
+                }
+                i += 1;
+            }
+            component.sort();
+            res.push(component);
+        }
+        res.sort();
+        res
+    }
+    fn cells(components: &[Component]) -> Vec<Vec<Point>>{
+        let mut res: Vec<Vec<Point>> = components.iter().map(|c|{
+            let mut points = c.tree.dump_all_points();
+            points.sort();
+            points
+        }).collect();
+        res.sort();
+        res
+    }
+    #[test]
+    fn test_reach() {
+        // a cell at the right edge reaches two columns into the leaf on its right
+        let cell = 1u64 << (8*3 + 7);
+        assert_eq!(reach(cell, 2, 1, 0), 0b11 << 16 | 0b11 << 24 | 0b11 << 32 | 0b11 << 40 | 0b11 << 8);
+        assert_eq!(reach(cell, 2, -1, 0), 0);
+        assert_eq!(leaf_pieces(0b101, 1).len(), 2);
+        assert_eq!(leaf_pieces(0b101, 2).len(), 1);
+    }
+    #[test]
+    fn test_matches_brute_force() {
+        // scattered cells across many leaves, on both sides of the origin
+        let mut points: Vec<Point> = (0..300).map(|i|Point{x: (i*37 % 101) - 50, y: (i*i*13 % 89) - 40}).collect();
+        points.sort();
+        points.dedup();
+        let tree = TreeData::gather_all_points(&points);
+        for distance in [1, 2, 3, 8]{
+            let components = tree.components(distance);
+            assert_eq!(cells(&components), brute_force(&points, distance), "distance {}", distance);
+            for c in components.iter(){
+                assert_eq!(c.population, c.tree.num_live_cells());
+                assert_eq!(Some(c.bounding_box), c.tree.bounding_box());
+            }
+        }
+        assert_eq!(cells(&tree.components(2)), separate_objects(&points));
+        assert!(TreeData::new().components(1).is_empty());
+    }
+    #[test]
+    fn test_far_apart() {
+        // blocks a long way apart, with nothing built for the space between them
+        let mut points = Vec::new();
+        for i in 0..1000i64{
+            let corner = Point{x: (i % 40) * 100000, y: (i / 40) * 100000 - 1000000};
+            for (dx, dy) in [(0, 0), (1, 0), (0, 1), (1, 1)]{
+                points.push(corner + Point{x: dx, y: dy});
+            }
+        }
+        let components = TreeData::gather_all_points(&points).components(2);
+        assert_eq!(components.len(), 1000);
+        assert!(components.iter().all(|c|c.population == 4 && c.bounding_box.1 + c.bounding_box.0.neg() == Point{x: 1, y: 1}));
+        assert_eq!(components[1].bounding_box.0, Point{x: 100000, y: -1000000});
+    }
+}
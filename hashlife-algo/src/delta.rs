@@ -0,0 +1,219 @@
+/*
+Incremental synchronisation of a TreeData between two copies, e.g.
+the web worker that steps the pattern and the UI that draws it.
+
+The sender remembers which nodes it has already sent and gives each
+an id in the order it was sent. A delta then only holds the new nodes,
+referring to older ones by id, so its size is proportional to what
+changed since the last delta rather than to the whole tree.
+
+Layout, in the same little endian encoding as serialize.rs:
+
+    magic       4 bytes   "HLQD"
+    version     u32
+    rule        u8 length and ASCII bytes
+    flags       u8        FLAG_RESET: the receiver drops every node it has
+    offset, depth, age    as in the full format
+    first id    u64       id of the first node in this delta
+    node count  u64
+    nodes       as in the full format, children refer to ids
+    root id     u64
+    checksum    u64
+*/
+
+use std::collections::HashMap;
+
+use crate::quadtree::TreeData;
+use crate::serialize::*;
+
+const MAGIC: &[u8; 4] = b"HLQD";
+const FLAG_RESET: u8 = 1;
+
+#[derive(Default)]
+pub struct DeltaSender{
+    sent: HashMap<u128, u64>,
+}
+impl DeltaSender{
+    pub fn new() -> DeltaSender{
+        DeltaSender::default()
+    }
+    // forget everything sent, the next delta carries the whole tree and
+    // lets the receiver drop its old nodes. Call this after garbage collection
+    // or when loading a new pattern so neither side keeps dead nodes around.
+    pub fn reset(&mut self){
+        self.sent.clear();
+    }
+    pub fn num_sent(&self) -> usize{
+        self.sent.len()
+    }
+}
+
+#[derive(Default)]
+pub struct DeltaReceiver{
+    keys: Vec<(u128, u64)>,
+}
+impl DeltaReceiver{
+    pub fn new() -> DeltaReceiver{
+        DeltaReceiver::default()
+    }
+}
+
+impl TreeData{
+    fn set_placement(&mut self, root: u128, placement: &Placement){
+        self.root = root;
+        self.depth = placement.depth;
+        self.offset = placement.offset;
+        self.age = placement.age;
+    }
+    pub fn serialize_delta(&self, sender: &mut DeltaSender) -> Vec<u8>{
+        let flags = if sender.sent.is_empty() {FLAG_RESET} else {0};
+        let first_id = sender.sent.len() as u64;
+        let mut nodes: Vec<u8> = Vec::new();
+        self.write_nodes(self.root, self.depth, &mut sender.sent, &mut nodes);
+
+        let mut res: Vec<u8> = Vec::with_capacity(nodes.len() + 80);
+        write_preamble(&mut res, MAGIC);
+        res.push(flags);
+        write_placement(&mut res, self);
+        res.extend_from_slice(&first_id.to_le_bytes());
+        res.extend_from_slice(&(sender.sent.len() as u64 - first_id).to_le_bytes());
+        res.extend_from_slice(&nodes);
+        res.extend_from_slice(&sender.sent[&self.root].to_le_bytes());
+        seal(&mut res);
+        res
+    }
+    // updates this tree to the one the delta describes. Deltas have to be
+    // applied to the same tree every time, without pruning it in between.
+    // On error the tree shows the same pattern as before.
+    pub fn apply_delta(&mut self, receiver: &mut DeltaReceiver, data: &[u8]) -> Result<(), DecodeError>{
+        let mut reader = open_blob(data, MAGIC)?;
+        let flags = reader.u8()?;
+        if flags & !FLAG_RESET != 0{
+            return Err(DecodeError::InvalidTree(format!("unknown flags {:#x}", flags)));
+        }
+        let placement = read_placement(&mut reader)?;
+        let first_id = reader.u64()?;
+        let count = reader.u64()?;
+        let reset = flags & FLAG_RESET != 0;
+        let known = if reset {0} else {receiver.keys.len()};
+        if first_id != known as u64{
+            return Err(DecodeError::InvalidTree(format!("delta starts at node {}, but {} nodes are known", first_id, known)));
+        }
+        check_node_count(&reader, count, 8)?;
+
+        if reset{
+            let mut tree = TreeData::new();
+            let mut keys = Vec::new();
+            let root = read_delta_body(&mut reader, &mut tree, &mut keys, count, placement.depth)?;
+            tree.set_placement(root, &placement);
+            *self = tree;
+            receiver.keys = keys;
+        }
+        else{
+            match read_delta_body(&mut reader, self, &mut receiver.keys, count, placement.depth){
+                Ok(root) => self.set_placement(root, &placement),
+                Err(err) => {
+                    // nodes already added to the tree are harmless, they are just not referenced
+                    receiver.keys.truncate(known);
+                    return Err(err);
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+fn read_delta_body(reader: &mut Reader, tree: &mut TreeData, keys: &mut Vec<(u128, u64)>, count: u64, depth: u64) -> Result<u128, DecodeError>{
+    for _ in 0..count{
+        read_node(reader, tree, keys, depth)?;
+    }
+    let root_id = reader.u64()?;
+    match keys.get(root_id as usize){
+        Some((key, level)) if *level == depth && tree.map.get(*key).is_some() => Ok(*key),
+        _ => Err(DecodeError::InvalidTree(format!("invalid root reference {}", root_id))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    // Note this useful idiom: importing names from outer (for mod tests) scope.
+    use super::*;
+    use crate::point::Point;
+
+    fn sorted_points(tree: &TreeData) -> Vec<Point>{
+        let mut res = tree.dump_all_points();
+        res.sort();
+        res
+    }
+    #[test]
+    fn test_delta_sync() {
+        let points = vec![Point{x:1, y:0}, Point{x:2, y:1}, Point{x:0, y:2}, Point{x:1, y:2}, Point{x:2, y:2},
+            Point{x:40, y:40}, Point{x:41, y:40}, Point{x:40, y:41}, Point{x:41, y:41}];
+        let mut tree = TreeData::gather_all_points(&points);
+        let mut sender = DeltaSender::new();
+        let mut receiver = DeltaReceiver::new();
+        let mut copy = TreeData::new();
+        let full = tree.serialize_delta(&mut sender);
+        copy.apply_delta(&mut receiver, &full).unwrap();
+        assert_eq!(sorted_points(&copy), sorted_points(&tree));
+        for _ in 0..10{
+            tree.step_forward(4);
+            let delta = tree.serialize_delta(&mut sender);
+            // the block and most of the empty space are never sent again
+            assert!(delta.len() < full.len());
+            copy.apply_delta(&mut receiver, &delta).unwrap();
+            assert_eq!(sorted_points(&copy), sorted_points(&tree));
+            assert_eq!(copy.get_age(), tree.get_age());
+        }
+        // an unchanged tree only resends the root reference
+        let empty = tree.serialize_delta(&mut sender);
+        copy.apply_delta(&mut receiver, &empty).unwrap();
+        assert_eq!(sorted_points(&copy), sorted_points(&tree));
+
+        sender.reset();
+        let reset = tree.pruned_tree().serialize_delta(&mut sender);
+        copy.apply_delta(&mut receiver, &reset).unwrap();
+        assert_eq!(sorted_points(&copy), sorted_points(&tree));
+        assert_eq!(receiver.keys.len(), sender.num_sent());
+    }
+    #[test]
+    fn test_delta_out_of_sequence() {
+        let mut tree = TreeData::gather_all_points(&vec![Point{x:0, y:0}, Point{x:1, y:0}, Point{x:2, y:0}]);
+        let mut sender = DeltaSender::new();
+        let mut receiver = DeltaReceiver::new();
+        let mut copy = TreeData::new();
+        let first = tree.serialize_delta(&mut sender);
+        tree.step_forward(1);
+        let second = tree.serialize_delta(&mut sender);
+        // skipping the first delta leaves references to unknown nodes
+        assert!(copy.apply_delta(&mut receiver, &second).is_err());
+        copy.apply_delta(&mut receiver, &first).unwrap();
+        copy.apply_delta(&mut receiver, &second).unwrap();
+        assert_eq!(sorted_points(&copy), sorted_points(&tree));
+    }
+    #[test]
+    fn test_delta_to_other_tree() {
+        // a receiver kept across a tree being replaced refers to nodes the new tree lacks,
+        // like those of the block that the next deltas do not resend
+        let mut tree = TreeData::gather_all_points(&vec![Point{x:0, y:0}, Point{x:1, y:0}, Point{x:2, y:0},
+            Point{x:40, y:40}, Point{x:41, y:40}, Point{x:40, y:41}, Point{x:41, y:41}]);
+        let mut sender = DeltaSender::new();
+        let mut receiver = DeltaReceiver::new();
+        let mut copy = TreeData::new();
+        copy.apply_delta(&mut receiver, &tree.serialize_delta(&mut sender)).unwrap();
+        tree.step_forward(1);
+        let delta = tree.serialize_delta(&mut sender);
+        // no new nodes, only the root reference
+        let unchanged = tree.serialize_delta(&mut sender);
+        let mut other = TreeData::gather_all_points(&vec![Point{x:100, y:-50}, Point{x:101, y:-50}]);
+        let before = sorted_points(&other);
+        assert!(other.apply_delta(&mut receiver, &delta).is_err());
+        assert_eq!(sorted_points(&other), before);
+        assert!(other.apply_delta(&mut receiver, &unchanged).is_err());
+        assert_eq!(sorted_points(&other), before);
+        // the right tree still takes both
+        copy.apply_delta(&mut receiver, &delta).unwrap();
+        copy.apply_delta(&mut receiver, &unchanged).unwrap();
+        assert_eq!(sorted_points(&copy), sorted_points(&tree));
+    }
+}
@@ -0,0 +1,162 @@
+/*
+Long exposures: how often each cell, or each square block of cells at a
+zoom level, was alive over a range of generations. Oscillators show up as
+their envelope, and gliders and signals as the paths they travel along.
+
+Frames are counted into the same pixels as make_grayscale_map. Close ups
+need a frame every generation to catch everything that moves, while a
+zoomed out view can take hashlife jumps between frames: a glider takes 4
+generations per cell, so a few frames still land in every pixel it
+crosses when the jump is as long as a pixel is wide.
+*/
+
+use crate::point::Point;
+use crate::quadtree::TreeData;
+
+// hash size above which the running copy is garbage collected
+const MAX_NODES: usize = 15000000;
+
+pub struct Exposure{
+    // cell at the top left of the image
+    pub offset: Point,
+    pub xsize: usize,
+    pub ysize: usize,
+    // each pixel covers 2^zoom x 2^zoom cells
+    pub zoom: u8,
+    // live cells seen in each pixel, summed over the frames
+    pub counts: Vec<u64>,
+    pub frames: u64,
+}
+
+impl Exposure{
+    pub fn new(offset: Point, xsize: usize, ysize: usize, zoom: u8) -> Exposure{
+        Exposure{offset, xsize, ysize, zoom, counts: vec![0; xsize*ysize], frames: 0}
+    }
+    // generations between frames for a zoom level, one per generation for close ups
+    pub fn default_step(zoom: u8) -> u64{
+        1 << zoom
+    }
+    pub fn add_frame(&mut self, tree: &TreeData){
+        tree.add_to_count_map(self.offset, self.xsize, self.ysize, self.zoom, &mut self.counts);
+        self.frames += 1;
+    }
+    // how often each pixel was alive, full brightness being always alive over the whole pixel
+    pub fn to_grayscale(&self, brightness: f64) -> Vec<u8>{
+        let area = (1u64 << (2*self.zoom as u64)) as f64;
+        let scale = 255.0 * brightness / (area * std::cmp::max(1, self.frames) as f64);
+        self.counts.iter().map(|count|(*count as f64 * scale).min(255.0) as u8).collect()
+    }
+    // pixels that were alive in any frame
+    pub fn envelope(&self) -> Vec<u8>{
+        self.counts.iter().map(|count|if *count > 0 {255} else {0}).collect()
+    }
+}
+
+impl TreeData{
+    // calls `frame` with the tree at each frame of the next `generations` generations
+    fn for_each_frame(&self, generations: u64, step: u64, mut frame: impl FnMut(&TreeData)){
+        assert!(step > 0);
+        let mut tree = self.pruned_tree();
+        let mut done = 0;
+        while done < generations{
+            frame(&tree);
+            let steps = std::cmp::min(step, generations - done);
+            tree.step_forward(steps);
+            done += steps;
+            if tree.hash_count() > MAX_NODES{
+                tree = tree.pruned_tree();
+            }
+        }
+    }
+    // exposure of the next `generations` generations, with a frame every `step` generations
+    // starting at the current one
+    pub fn long_exposure(&self, offset: Point, xsize: usize, ysize: usize, zoom: u8, generations: u64, step: u64) -> Exposure{
+        let mut exposure = Exposure::new(offset, xsize, ysize, zoom);
+        self.for_each_frame(generations, step, |tree|exposure.add_frame(tree));
+        exposure
+    }
+    // union of the bounding boxes of the frames long_exposure takes, None if they are all empty.
+    // Frames every 2^k generations are among those every 2^j for j <= k, so the bounds
+    // at a finer step also hold for coarser ones
+    pub fn exposure_bounds(&self, generations: u64, step: u64) -> Option<(Point, Point)>{
+        let mut bounds: Option<(Point, Point)> = None;
+        self.for_each_frame(generations, step, |tree|{
+            if let Some((lo, hi)) = tree.bounding_box(){
+                bounds = Some(match bounds{
+                    Some((min, max)) => (Point{x: min.x.min(lo.x), y: min.y.min(lo.y)}, Point{x: max.x.max(hi.x), y: max.y.max(hi.y)}),
+                    None => (lo, hi),
+                });
+            }
+        });
+        bounds
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    // Note this useful idiom: importing names from outer (for mod tests) scope.
+    use super::*;
+    use crate::rle::parse_rle;
+
+    fn load(rle: &str) -> TreeData{
+        TreeData::from_pattern(&parse_rle(rle).unwrap())
+    }
+    fn lit(image: &[u8], xsize: usize) -> Vec<(usize, usize)>{
+        image.iter().enumerate().filter(|(_, v)|**v > 0).map(|(i, _)|(i % xsize, i / xsize)).collect()
+    }
+    #[test]
+    fn test_blinker_envelope() {
+        // both phases of a blinker make a plus, with the centre always alive
+        let exposure = load("x = 3, y = 1\n3o!\n").long_exposure(Point{x: -1, y: -1}, 5, 3, 0, 2, 1);
+        assert_eq!(exposure.frames, 2);
+        assert_eq!(lit(&exposure.envelope(), 5), vec![(2, 0), (1, 1), (2, 1), (3, 1), (2, 2)]);
+        let image = exposure.to_grayscale(1.0);
+        assert_eq!((image[5 + 2], image[5 + 1], image[2]), (255, 127, 127));
+    }
+    #[test]
+    fn test_matches_frames() {
+        // the exposure is the sum of the count maps of each frame, for close ups and zoomed out
+        let glider = load("x = 3, y = 3\nbo$2bo$3o!\n");
+        for (zoom, step) in [(0, 1), (2, 4), (3, 8)]{
+            let exposure = glider.long_exposure(Point{x: 0, y: 0}, 40, 40, zoom, 100, step);
+            let mut expected = vec![0; 40*40];
+            let mut tree = glider.pruned_tree();
+            for _ in 0..exposure.frames{
+                tree.add_to_count_map(Point{x: 0, y: 0}, 40, 40, zoom, &mut expected);
+                tree.step_forward(step);
+            }
+            assert_eq!(exposure.counts, expected);
+            assert_eq!(exposure.frames, 100u64.div_ceil(step));
+            assert_eq!(exposure.counts.iter().sum::<u64>(), 5*exposure.frames);
+        }
+        // close up, the envelope is every cell alive at some generation
+        let mut cells = std::collections::HashSet::new();
+        let mut tree = glider.pruned_tree();
+        for _ in 0..40{
+            cells.extend(tree.dump_all_points().into_iter().map(|p|(p.x as usize, p.y as usize)));
+            tree.step_forward(1);
+        }
+        let envelope = lit(&glider.long_exposure(Point{x: 0, y: 0}, 16, 16, 0, 40, 1).envelope(), 16);
+        assert_eq!(envelope.len(), cells.len());
+        assert!(envelope.iter().all(|p|cells.contains(p)));
+        // a glider moving one cell diagonally every 4 generations leaves a diagonal trail
+        let trail = lit(&glider.long_exposure(Point{x: 0, y: 0}, 16, 16, 2, 200, 4).envelope(), 16);
+        assert!((0..12).all(|i|trail.contains(&(i, i))));
+        assert!(trail.iter().all(|(x, y)|x.abs_diff(*y) <= 1));
+    }
+    #[test]
+    fn test_exposure_bounds() {
+        // the vertical phase of a blinker sticks out of the box of the horizontal one
+        let blinker = load("x = 3, y = 1\n3o!\n");
+        let (lo, hi) = blinker.bounding_box().unwrap();
+        assert_eq!(blinker.exposure_bounds(1, 1), Some((lo, hi)));
+        assert_eq!(blinker.exposure_bounds(2, 2), Some((lo, hi)));
+        let middle = Point{x: lo.x + 1, y: lo.y};
+        assert_eq!(blinker.exposure_bounds(3, 1), Some((Point{x: lo.x, y: middle.y - 1}, Point{x: hi.x, y: middle.y + 1})));
+        // a glider covers the boxes of the frames taken, not the one it ends up in
+        let glider = load("x = 3, y = 3\nbo$2bo$3o!\n");
+        assert_eq!(glider.exposure_bounds(8, 4), Some((Point{x: 0, y: 0}, Point{x: 3, y: 3})));
+        assert_eq!(TreeData::new().exposure_bounds(10, 1), None);
+        assert_eq!(glider.exposure_bounds(0, 1), None);
+    }
+}
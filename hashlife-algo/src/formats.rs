@@ -0,0 +1,147 @@
+/*
+Detection of and dispatch between the supported pattern file formats.
+*/
+
+use crate::cells::{parse_cells, write_cells};
+use crate::life::*;
+use crate::parse_error::ParseError;
+use crate::quadtree::TreeData;
+use crate::rle::{parse_rle, write_rle_pattern, Pattern, PatternMetadata};
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum PatternFormat{
+    Rle,
+    Cells,
+    Life105,
+    Life106,
+    Macrocell,
+}
+
+impl PatternFormat{
+    // the format a file name suggests, Life 1.06 is assumed for `.lif` and `.life`
+    pub fn from_file_name(fname: &str) -> Option<PatternFormat>{
+        let lower = fname.to_ascii_lowercase();
+        if lower.ends_with(".rle"){
+            Some(PatternFormat::Rle)
+        } else if lower.ends_with(".cells"){
+            Some(PatternFormat::Cells)
+        } else if lower.ends_with(".lif") || lower.ends_with(".life"){
+            Some(PatternFormat::Life106)
+        } else if lower.ends_with(".mc") || lower.ends_with(".mc.gz"){
+            Some(PatternFormat::Macrocell)
+        } else {
+            None
+        }
+    }
+}
+
+fn is_rle_header(line: &str) -> bool{
+    line.trim_start().starts_with('x') && line.contains('=')
+}
+fn is_grid_row(line: &str) -> bool{
+    line.chars().all(|c|c == '.' || c == 'O' || c == '*')
+}
+fn is_coordinate_pair(line: &str) -> bool{
+    let fields: Vec<&str> = line.split_whitespace().collect();
+    fields.len() == 2 && fields.iter().all(|v|v.parse::<i64>().is_ok())
+}
+
+pub fn detect_format(file_contents: &str) -> PatternFormat{
+    let first_line = file_contents.lines().find(|l|!l.trim().is_empty()).unwrap_or("").trim();
+    if first_line.starts_with("[M2]"){
+        return PatternFormat::Macrocell;
+    }
+    if first_line.starts_with(LIFE_106_HEADER){
+        return PatternFormat::Life106;
+    }
+    if first_line.starts_with(LIFE_105_HEADER){
+        return PatternFormat::Life105;
+    }
+    if first_line.starts_with('!'){
+        return PatternFormat::Cells;
+    }
+    let body: Vec<&str> = file_contents.lines()
+        .map(|l|l.trim_end())
+        .filter(|l|!l.is_empty() && !l.starts_with('#'))
+        .collect();
+    match body.first(){
+        Some(line) if is_rle_header(line) => PatternFormat::Rle,
+        Some(_) if body.iter().all(|l|is_grid_row(l)) => PatternFormat::Cells,
+        Some(_) if body.iter().all(|l|is_coordinate_pair(l)) => PatternFormat::Life106,
+        // anything else is reported with RLE's error messages
+        _ => PatternFormat::Rle,
+    }
+}
+
+pub fn parse_pattern_as(file_contents: &str, format: PatternFormat) -> Result<Pattern, ParseError>{
+    match format{
+        PatternFormat::Rle => parse_rle(file_contents),
+        PatternFormat::Cells => parse_cells(file_contents),
+        PatternFormat::Life105 => parse_life105(file_contents),
+        PatternFormat::Life106 => parse_life106(file_contents),
+        PatternFormat::Macrocell => TreeData::read_macrocell(file_contents)
+            .map(|tree|tree.to_pattern(&PatternMetadata::default())),
+    }
+}
+
+pub fn parse_pattern(file_contents: &str) -> Result<Pattern, ParseError>{
+    parse_pattern_as(file_contents, detect_format(file_contents))
+}
+
+pub fn write_pattern(pattern: &Pattern, format: PatternFormat) -> String{
+    match format{
+        PatternFormat::Rle => write_rle_pattern(pattern),
+        PatternFormat::Cells => write_cells(pattern),
+        PatternFormat::Life105 => write_life105(pattern),
+        PatternFormat::Life106 => write_life106(pattern),
+        PatternFormat::Macrocell => TreeData::from_pattern(pattern).write_macrocell(),
+    }
+}
+
+impl TreeData{
+    // loads a pattern in any of the supported text formats, macrocell
+    // files are read straight into the tree without listing their cells
+    pub fn load_pattern_text(file_contents: &str) -> Result<(TreeData, PatternMetadata), ParseError>{
+        match detect_format(file_contents){
+            PatternFormat::Macrocell => TreeData::read_macrocell(file_contents)
+                .map(|tree|(tree, PatternMetadata::default())),
+            format => parse_pattern_as(file_contents, format)
+                .map(|pattern|(TreeData::from_pattern(&pattern), pattern.metadata)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    // Note this useful idiom: importing names from outer (for mod tests) scope.
+    use super::*;
+
+    #[test]
+    fn test_detect_format() {
+        assert_eq!(detect_format("#C glider\nx = 3, y = 3\nbo$2bo$3o!\n"), PatternFormat::Rle);
+        assert_eq!(detect_format("!Name: Glider\n.O\n..O\nOOO\n"), PatternFormat::Cells);
+        assert_eq!(detect_format(".O\n..O\nOOO\n"), PatternFormat::Cells);
+        assert_eq!(detect_format("#Life 1.05\n#P 0 0\n.*\n"), PatternFormat::Life105);
+        assert_eq!(detect_format("#Life 1.06\n0 1\n"), PatternFormat::Life106);
+        assert_eq!(detect_format("0 1\n1 1\n"), PatternFormat::Life106);
+        assert_eq!(detect_format("[M2] (golly 4.2)\n#R B3/S23\n"), PatternFormat::Macrocell);
+        assert_eq!(detect_format("bo$2bo$3o!\n"), PatternFormat::Rle);
+    }
+    #[test]
+    fn test_formats_agree() {
+        let rle = "x = 3, y = 3, rule = B3/S23\nbo$2bo$3o!\n";
+        let mut expected = parse_rle(rle).unwrap().absolute_points();
+        expected.sort();
+        let pattern = parse_pattern(rle).unwrap();
+        for format in [PatternFormat::Rle, PatternFormat::Cells, PatternFormat::Life105, PatternFormat::Life106, PatternFormat::Macrocell]{
+            let text = write_pattern(&pattern, format);
+            assert_eq!(detect_format(&text), format);
+            let (tree, _) = TreeData::load_pattern_text(&text).unwrap();
+            let mut points = tree.dump_all_points();
+            let corner = tree.bounding_box().unwrap().0;
+            points = points.iter().map(|p|*p + corner.neg()).collect();
+            points.sort();
+            assert_eq!(points, expected);
+        }
+    }
+}
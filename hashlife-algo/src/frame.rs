@@ -0,0 +1,197 @@
+/*
+Re-framing of the quadtree: extracting the node that covers an
+arbitrary, unaligned square of the universe, and finding the
+bounding box of the live cells.
+
+Both work on the node DAG and memoise per node, so they cost
+time proportional to the number of distinct nodes rather
+than the number of cells.
+*/
+
+use std::collections::HashMap;
+
+use crate::point::Point;
+use crate::quadtree::TreeData;
+use crate::raw_ops::*;
+
+// cached results of window extraction, keyed by the 2x2 block of nodes and the shift into it
+pub(crate) type WindowCache = HashMap<([u128;4], i64, i64), u128>;
+
+pub(crate) fn node_size(depth: u64) -> i64{
+    8 << depth
+}
+fn leaf_row(leaf: u128, row: i64) -> u64{
+    ((leaf as u64) >> (8*row)) & 0xff
+}
+fn window_raw(quad: [u128;4], dx: i64, dy: i64) -> u128{
+    // quad is a 16x16 block of four 8x8 leaves, returns the 8x8 leaf at (dx, dy)
+    let mut res: u64 = 0;
+    for row in 0..8{
+        let y = row + dy;
+        let (left, right) = if y < 8 {(quad[0], quad[1])} else {(quad[2], quad[3])};
+        let line = leaf_row(left, y % 8) | (leaf_row(right, y % 8) << 8);
+        res |= ((line >> dx) & 0xff) << (8*row);
+    }
+    res as u128
+}
+fn leaf_bounding_box(leaf: u64) -> Option<(Point, Point)>{
+    if leaf == 0{
+        return None;
+    }
+    let rows: Vec<i64> = (0..8).filter(|y|(leaf >> (8*y)) & 0xff != 0).collect();
+    let cols = (0..8).fold(0, |acc, y|acc | ((leaf >> (8*y)) & 0xff));
+    Some((
+        Point{x: cols.trailing_zeros() as i64, y: rows[0]},
+        Point{x: 7 - (cols as u8).leading_zeros() as i64, y: rows[rows.len()-1]},
+    ))
+}
+fn merge_boxes(a: Option<(Point, Point)>, b: Option<(Point, Point)>) -> Option<(Point, Point)>{
+    match (a, b){
+        (None, b) => b,
+        (a, None) => a,
+        (Some((amin, amax)), Some((bmin, bmax))) => Some((
+            Point{x: amin.x.min(bmin.x), y: amin.y.min(bmin.y)},
+            Point{x: amax.x.max(bmax.x), y: amax.y.max(bmax.y)},
+        )),
+    }
+}
+
+impl TreeData{
+    pub(crate) fn is_empty_node(&self, key: u128, depth: u64) -> bool{
+        // raw leaves are not stored in the map, so they can only be checked directly
+        if depth == 0 {key == 0} else {self.is_black(key)}
+    }
+    pub(crate) fn children(&self, key: u128) -> [u128;4]{
+        self.map.get(key).unwrap().v.to_array()
+    }
+    pub(crate) fn grandchildren(&self, key: u128) -> [u128;16]{
+        // 4x4 grid of the grandchildren of a node, in row major order
+        let init_map = self.children(key).map(|x|self.children(x));
+        let arg_map = unsafe{std::mem::transmute::<[[u128;4]; 4], [u128;16]>(init_map)};
+        transpose_quad(&arg_map)
+    }
+    fn quad_grid(&self, quad: [u128;4]) -> [u128;16]{
+        // 4x4 grid of the children of a 2x2 block of nodes
+        let arg_map = unsafe{std::mem::transmute::<[[u128;4]; 4], [u128;16]>(quad.map(|x|self.children(x)))};
+        transpose_quad(&arg_map)
+    }
+    pub(crate) fn window_key(&mut self, quad: [u128;4], depth: u64, dx: i64, dy: i64, cache: &mut WindowCache) -> u128{
+        // quad is a 2x2 block of nodes at `depth`, returns the node at `depth`
+        // whose top left corner is (dx, dy) inside the block
+        let size = node_size(depth);
+        debug_assert!(0 <= dx && dx <= size && 0 <= dy && dy <= size);
+        if dx % size == 0 && dy % size == 0{
+            return quad[(dy/size*2 + dx/size) as usize];
+        }
+        if quad.iter().all(|k|self.is_empty_node(*k, depth)){
+            return self.black_key(depth as usize);
+        }
+        if let Some(key) = cache.get(&(quad, dx, dy)){
+            return *key;
+        }
+        let key = if depth == 0{
+            window_raw(quad, dx, dy)
+        }
+        else{
+            let half = size / 2;
+            let grid = self.quad_grid(quad);
+            let mut children = [0u128;4];
+            for (i, child) in children.iter_mut().enumerate(){
+                let ox = dx + (i as i64 % 2)*half;
+                let oy = dy + (i as i64 / 2)*half;
+                let gx = std::cmp::min(ox / half, 2);
+                let gy = std::cmp::min(oy / half, 2);
+                let sub = slice(&grid, gx as usize, gy as usize);
+                *child = self.window_key(sub, depth-1, ox - gx*half, oy - gy*half, cache);
+            }
+            self.add_array(children)
+        };
+        cache.insert((quad, dx, dy), key);
+        key
+    }
+    pub(crate) fn frame_key(&mut self, top_left: Point, depth: u64, cache: &mut WindowCache) -> u128{
+        // the node at `depth` whose top left corner is at `top_left`,
+        // cells of the universe outside of that square are dropped
+        let size = node_size(depth);
+        while self.depth < depth + 1
+            || top_left.x < self.offset.x || top_left.y < self.offset.y
+            || top_left.x + size > self.offset.x + node_size(self.depth)
+            || top_left.y + size > self.offset.y + node_size(self.depth){
+            self.increase_depth();
+        }
+        let mut cur = self.root;
+        let mut cur_depth = self.depth;
+        let mut rel = top_left + self.offset.neg();
+        while cur_depth > depth + 1{
+            // zoom into the 2x2 block of grandchildren that holds the frame
+            let quarter = node_size(cur_depth - 2);
+            let gx = std::cmp::min(rel.x / quarter, 2);
+            let gy = std::cmp::min(rel.y / quarter, 2);
+            let grid = self.grandchildren(cur);
+            cur = self.add_array(slice(&grid, gx as usize, gy as usize));
+            cur_depth -= 1;
+            rel = rel + Point{x: -gx*quarter, y: -gy*quarter};
+        }
+        let children = self.children(cur);
+        self.window_key(children, depth, rel.x, rel.y, cache)
+    }
+    fn node_bounding_box(&self, key: u128, depth: u64, cache: &mut HashMap<u128, Option<(Point, Point)>>) -> Option<(Point, Point)>{
+        // bounding box relative to the node's top left corner
+        if depth == 0{
+            return leaf_bounding_box(key as u64);
+        }
+        if self.is_empty_node(key, depth){
+            return None;
+        }
+        if let Some(bbox) = cache.get(&key){
+            return *bbox;
+        }
+        let half = node_size(depth - 1);
+        let mut bbox = None;
+        for (i, child) in self.children(key).iter().enumerate(){
+            let shift = Point{x: (i as i64 % 2)*half, y: (i as i64 / 2)*half};
+            let child_box = self.node_bounding_box(*child, depth-1, cache)
+                .map(|(min, max)|(min + shift, max + shift));
+            bbox = merge_boxes(bbox, child_box);
+        }
+        cache.insert(key, bbox);
+        bbox
+    }
+    // smallest rectangle holding every live cell, as (top left, bottom right) inclusive corners
+    pub fn bounding_box(&self) -> Option<(Point, Point)>{
+        let mut cache = HashMap::new();
+        self.node_bounding_box(self.root, self.depth, &mut cache)
+            .map(|(min, max)|(min + self.offset, max + self.offset))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    // Note this useful idiom: importing names from outer (for mod tests) scope.
+    use super::*;
+
+    fn sorted(points: &[Point]) -> Vec<Point>{
+        let mut res = points.to_vec();
+        res.sort();
+        res
+    }
+    #[test]
+    fn test_bounding_box() {
+        let points = vec![Point{x:-40, y:3}, Point{x:17, y:-9}, Point{x:2, y:100}];
+        let tree = TreeData::gather_all_points(&points);
+        assert_eq!(tree.bounding_box(), Some((Point{x:-40, y:-9}, Point{x:17, y:100})));
+        assert_eq!(TreeData::new().bounding_box(), None);
+    }
+    #[test]
+    fn test_frame_key() {
+        let points = vec![Point{x:-5, y:3}, Point{x:-4, y:3}, Point{x:9, y:20}, Point{x:30, y:-2}];
+        let mut tree = TreeData::gather_all_points(&points);
+        let mut cache = WindowCache::new();
+        let top_left = Point{x:-13, y:-7};
+        let key = tree.frame_key(top_left, 3, &mut cache);
+        tree.root = key;
+        tree.depth = 3;
+        tree.offset = top_left;
+        assert_eq!(sorted(&tree.dump_all_points()), sorted(&points));
+    }
+}
@@ -0,0 +1,255 @@
+/*
+Classification of how a pattern grows: dying out, staying bounded,
+growing linearly like a gun or puffer, or faster like a breeder.
+
+Gliders and other debris a pattern leaves behind keep their population,
+so a pattern that repeats apart from its debris has a population whose
+differences over the period repeat: the population itself for bounded
+patterns, its growth per period for guns and puffers, and the change in
+that growth for quadratic breeders. The lowest such order and the
+shortest period are found on a window of single generations, then checked
+again after a long hashlife jump, like the stabilisation search does.
+
+Populations can repeat by chance, so the pattern itself is checked too.
+Objects that are still lifes, oscillators or spaceships on their own are
+the debris. The rest is the engine, and one period later every engine
+object has to be back, either in place like a gun or a gun laid by a
+breeder, or moved by the one displacement of the puffers.
+
+The growth exponent is fitted to the population sampled at hashlife jumps
+over the second half of the run, and gives the class when the differences
+do not repeat within the allowed period.
+*/
+
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+
+use crate::census::{normalized, separate_objects};
+use crate::point::Point;
+use crate::quadtree::TreeData;
+
+// shortest jump used to check the differences keep repeating
+const MIN_LOOKAHEAD: u64 = 1024;
+// population samples taken for the exponent fit
+const FIT_SAMPLES: u64 = 16;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum GrowthClass{
+    Dies,
+    Bounded,
+    // guns and puffers
+    Linear,
+    // breeders
+    Superlinear,
+}
+
+impl fmt::Display for GrowthClass{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result{
+        let name = match self{
+            GrowthClass::Dies => "dies",
+            GrowthClass::Bounded => "bounded",
+            GrowthClass::Linear => "linear growth",
+            GrowthClass::Superlinear => "superlinear growth",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Growth{
+    pub class: GrowthClass,
+    // period at which the pattern repeats apart from its debris, or of the pattern itself when it
+    // is periodic as a whole. None when the class comes from the fitted exponent alone
+    pub period: Option<u64>,
+    // cells gained per period for linear growth, the change in that gain per period for
+    // quadratic growth, 0 otherwise
+    pub growth: i64,
+    // slope of log population against log generation
+    pub exponent: f64,
+}
+
+// `order`-th difference of the populations with step `period`, starting at `t`
+fn difference(pops: &[u64], t: usize, period: usize, order: usize) -> i64{
+    match order{
+        0 => pops[t] as i64,
+        _ => difference(pops, t + period, period, order - 1) - difference(pops, t, period, order - 1),
+    }
+}
+
+// lowest order and then shortest period at which the differences of the populations repeat
+fn repeating_difference(pops: &[u64], max_period: u64) -> Option<(u64, usize)>{
+    for order in 0..=2{
+        for period in 1..=max_period as usize{
+            let span = (order + 1) * period;
+            if span + period > pops.len(){
+                break;
+            }
+            if (0..pops.len() - span).all(|t|difference(pops, t, period, order + 1) == 0){
+                return Some((period as u64, order));
+            }
+        }
+    }
+    None
+}
+
+fn fitted_exponent(samples: &[(u64, u64)]) -> f64{
+    let points: Vec<(f64, f64)> = samples.iter()
+        .filter(|(gen, pop)|*gen > 0 && *pop > 0)
+        .map(|(gen, pop)|((*gen as f64).ln(), (*pop as f64).ln()))
+        .collect();
+    if points.len() < 2{
+        return 0.0;
+    }
+    let n = points.len() as f64;
+    let mean_x = points.iter().map(|p|p.0).sum::<f64>() / n;
+    let mean_y = points.iter().map(|p|p.1).sum::<f64>() / n;
+    let cov: f64 = points.iter().map(|p|(p.0 - mean_x)*(p.1 - mean_y)).sum();
+    let var: f64 = points.iter().map(|p|(p.0 - mean_x)*(p.0 - mean_x)).sum();
+    if var == 0.0 {0.0} else {cov / var}
+}
+
+fn shifted(object: &[Point], offset: Point) -> Vec<Point>{
+    object.iter().map(|p|*p + offset).collect()
+}
+
+impl TreeData{
+    // the objects that do not repeat within `max_period` generations on their own,
+    // remembering in `periodic` which shapes do
+    fn engine_objects(&self, max_period: u64, periodic: &mut HashMap<Vec<Point>, bool>) -> HashSet<Vec<Point>>{
+        separate_objects(&self.dump_all_points()).into_iter().filter(|object|{
+            !*periodic.entry(normalized(object)).or_insert_with(||{
+                TreeData::gather_all_points(object).find_period(max_period).is_some()
+            })
+        }).collect()
+    }
+    // whether the engine objects come back after `period` generations, each in place or
+    // moved by one displacement common to all of them
+    fn repeats_modulo_debris(&self, period: u64, max_period: u64) -> bool{
+        let mut periodic = HashMap::new();
+        let mut tree = self.pruned_tree();
+        let before = tree.engine_objects(max_period, &mut periodic);
+        tree.step_forward(period);
+        let after = tree.engine_objects(max_period, &mut periodic);
+        let covered = |from: &HashSet<Vec<Point>>, to: &HashSet<Vec<Point>>, offset: Point|{
+            from.iter().all(|object|to.contains(object) || to.contains(&shifted(object, offset)))
+        };
+        // an object that is not in place fixes the displacement, up to which object of its shape it came from
+        let offsets: Vec<Point> = match after.iter().find(|object|!before.contains(*object)){
+            Some(moved) => {
+                let shape = normalized(moved);
+                before.iter().filter(|object|normalized(object) == shape).map(|object|moved[0] + object[0].neg()).collect()
+            },
+            None => vec![Point{x: 0, y: 0}],
+        };
+        offsets.into_iter().any(|offset|covered(&before, &after, offset) && covered(&after, &before, offset.neg()))
+    }
+    // classifies the growth of the pattern after running it for `horizon` generations,
+    // looking for differences repeating with a period of at most `max_period`
+    pub fn classify_growth(&self, horizon: u64, max_period: u64) -> Growth{
+        assert!(max_period > 0);
+        let mut tree = self.pruned_tree();
+        let start = tree.get_age();
+        let mut samples = Vec::new();
+        for i in 1..=FIT_SAMPLES{
+            let steps = horizon * i / FIT_SAMPLES - (tree.get_age() - start);
+            if steps > 0{
+                tree.step_forward(steps);
+            }
+            samples.push((tree.get_age() - start, tree.num_live_cells()));
+        }
+        let exponent = fitted_exponent(&samples[samples.len()/2..]);
+        if tree.num_live_cells() == 0{
+            return Growth{class: GrowthClass::Dies, period: None, growth: 0, exponent};
+        }
+        let window = 4*max_period;
+        let pops = tree.populations(window);
+        // the pattern may only repeat at a multiple of the period of its differences,
+        // like a switch engine that comes back mirrored after half its period
+        let repeating = repeating_difference(&pops, max_period).and_then(|(period, order)|{
+            (1..=max_period / period).map(|k|k*period).find(|period|{
+                // the same differences have to come back after a long jump
+                let period = *period;
+                let jump = MIN_LOOKAHEAD.max(horizon).max(window).div_ceil(period) * period;
+                let mut later = tree.pruned_tree();
+                later.step_forward(jump - (window - 1));
+                let later_pops = later.populations((order as u64 + 1) * period);
+                (0..period as usize).all(|t|difference(&later_pops, t, period as usize, order) == difference(&pops, t, period as usize, order))
+                    && later.repeats_modulo_debris(period, max_period)
+            }).map(|period|(period, order))
+        });
+        match repeating{
+            Some((period, order)) => Growth{
+                class: [GrowthClass::Bounded, GrowthClass::Linear, GrowthClass::Superlinear][order],
+                // a whole pattern that repeats may do so less often than its population
+                period: Some(match order{
+                    0 => tree.find_period(max_period).map_or(period, |p|p.period),
+                    _ => period,
+                }),
+                growth: if order == 0 {0} else {difference(&pops, 0, period as usize, order)},
+                exponent,
+            },
+            None => Growth{
+                class: if exponent < 0.5 {GrowthClass::Bounded} else if exponent < 1.5 {GrowthClass::Linear} else {GrowthClass::Superlinear},
+                period: None,
+                growth: 0,
+                exponent,
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    // Note this useful idiom: importing names from outer (for mod tests) scope.
+    use super::*;
+    use crate::rle::parse_rle;
+
+    const GOSPER_GUN: &str = "x = 36, y = 9\n24bo$22bobo$12b2o6b2o12b2o$11bo3bo4b2o12b2o$2o8bo5bo3b2o$2o8bo3bob2o4bobo$10bo5bo7bo$11bo3bo$12b2o!\n";
+
+    fn classify(rle: &str, horizon: u64, max_period: u64) -> Growth{
+        TreeData::from_pattern(&parse_rle(rle).unwrap()).classify_growth(horizon, max_period)
+    }
+    #[test]
+    fn test_repeating_difference() {
+        // a period 3 wobble on top of quadratic growth
+        let pops: Vec<u64> = (0..40u64).map(|t|100 + (t/3)*(t/3) + [0, 2, 1][(t % 3) as usize]).collect();
+        assert_eq!(repeating_difference(&pops, 10), Some((3, 2)));
+        let linear: Vec<u64> = (0..40u64).map(|t|50 + 5*(t/6)).collect();
+        assert_eq!(repeating_difference(&linear, 10), Some((6, 1)));
+        assert_eq!(repeating_difference(&[4, 3, 4, 3, 4, 3, 4, 3], 2), Some((2, 0)));
+    }
+    #[test]
+    fn test_gun() {
+        let growth = classify(GOSPER_GUN, 60, 30);
+        assert_eq!(growth.class, GrowthClass::Linear);
+        assert_eq!(growth.period, Some(30));
+        // one glider of 5 cells per period
+        assert_eq!(growth.growth, 5);
+    }
+    #[test]
+    fn test_repeats_modulo_debris() {
+        let load = |rle: &str|TreeData::from_pattern(&parse_rle(rle).unwrap());
+        let mut gun = load(GOSPER_GUN);
+        gun.step_forward(200);
+        assert!(gun.repeats_modulo_debris(30, 30));
+        // half a period later the shuttle is in its mirrored phase
+        assert!(!gun.repeats_modulo_debris(15, 30));
+        // a block laying switch engine comes back mirrored after half of its period
+        let mut engine = load("x = 8, y = 6\n6bob$4bob2o$4bobob$4bo3b$2bo5b$obo!\n");
+        engine.step_forward(2000);
+        assert!(!engine.repeats_modulo_debris(144, 4));
+        assert!(engine.repeats_modulo_debris(288, 4));
+        // debris alone, a glider flying away from a blinker
+        assert!(load("x = 13, y = 3\nbo$2bo7b3o$3o!\n").repeats_modulo_debris(4, 4));
+        // an R-pentomino is nowhere near repeating
+        assert!(!load("x = 3, y = 3\nb2o$2o$bo!\n").repeats_modulo_debris(2, 4));
+    }
+    #[test]
+    fn test_bounded_and_dying() {
+        let blinker = classify("x = 3, y = 1\n3o!\n", 16, 4);
+        assert_eq!((blinker.class, blinker.period, blinker.growth), (GrowthClass::Bounded, Some(2), 0));
+        let diehard = classify("x = 8, y = 3\n6bo$2o$bo3b3o!\n", 200, 4);
+        assert_eq!(diehard.class, GrowthClass::Dies);
+        assert_eq!(GrowthClass::Superlinear.to_string(), "superlinear growth");
+    }
+}
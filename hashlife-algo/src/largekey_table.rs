@@ -120,17 +120,6 @@ impl<T: Copy + Default> LargeKeyTable<T>{
     //         }
     //     }
     // }
-    
-    pub fn iter<F>(& self, func: &mut F)
-    where
-        F: FnMut(&u128, &T)->bool
-    {
-        for item in self.arena.iter(){
-            if !func(&item.key, &item.value) {
-                break;
-            }
-        }
-    }
 }
 
 #[cfg(test)]
@@ -1,14 +1,64 @@
 mod point;
+mod parse_error;
 mod rle;
+mod cells;
+mod life;
+mod formats;
 mod quadtree;
+mod frame;
+mod macrocell;
+mod period;
+mod stabilise;
+mod apgcode;
+mod census;
+mod sha256;
+mod soup;
+mod transform;
+mod boolean;
+mod compare;
+mod subpattern;
+mod probe;
+mod breakpoint;
+mod stats;
+mod growth;
+mod oscillator;
+mod collision;
+mod component;
+mod sat;
+mod predecessor;
+mod exposure;
 mod largekey_table;
 mod raw_ops;
 mod serialize;
+mod delta;
 mod typedarena;
 
 pub use crate::point::{Point};
 pub use crate::quadtree::{TreeData};
 pub use crate::rle::*;
+pub use crate::cells::{parse_cells, write_cells};
+pub use crate::life::{parse_life105, write_life105, parse_life106, write_life106};
+pub use crate::formats::*;
+pub use crate::serialize::DecodeError;
+pub use crate::delta::{DeltaSender, DeltaReceiver};
+pub use crate::period::{Periodicity, PeriodDetector};
+pub use crate::stabilise::Stabilisation;
+pub use crate::apgcode::{canonical_wechsler, wechsler, decode_apgcode};
+pub use crate::soup::{Symmetry, SoupParams, soup_points};
+pub use crate::census::{Census, HaulHeader, separate_objects, classify_object, UNKNOWN_OBJECT};
+pub use crate::transform::Transform;
+pub use crate::boolean::BoolOp;
+pub use crate::compare::Equivalence;
+pub use crate::subpattern::{FinderOptions, Match, PatternFinder};
+pub use crate::probe::{Probe, ProbeEvent, ProbeRecording};
+pub use crate::breakpoint::StopCondition;
+pub use crate::stats::{Sample, TimeSeries};
+pub use crate::growth::{Growth, GrowthClass};
+pub use crate::oscillator::OscillatorStats;
+pub use crate::component::Component;
+pub use crate::collision::{Collision, CollisionOptions, group_by_outcome, CLEAN_KILL, UNSTABLE};
+pub use crate::predecessor::{Predecessor, PredecessorOptions};
+pub use crate::exposure::Exposure;
 
 pub fn tile_bytes(arr:&[u8],xsize:usize,tile:usize)->Vec<u8>{
     //use to zoom up the grayscale map
@@ -127,7 +177,7 @@ mod tests {
             "5bob2o$4bo6bo$3b2o3bo2bo$2obo5b2o$2obo5b2o$3b2o3bo2bo$4bo6bo$5bob2o!\n"
         );
 
-        let points = parse_fle_file(contents);
+        let points = parse_fle_file(contents).unwrap();
         let mut tree = TreeData::gather_all_points(&points);
         let out_points = tree.dump_all_points();
         let rle_tot_str = write_rle(&out_points);
@@ -145,13 +195,35 @@ mod tests {
             "12bo8bo$bo2bo2b2o2bo25bo2b2o2bo2bo$6bo5bo7b3o3b3o7bo5bo$6bo5bo8bo5bo8b\no5bo$6bo5bo8b7o8bo5bo$bo2bo2b2o2bo2b2o4bo7bo4b2o2bo2b2o2bo2bo$o8bo3b2o\n4b11o4b2o3bo8bo$o3bo9b2o17b2o9bo3bo$4o11b19o11b4o$16bobo11bobo$19b11o$\n19bo9bo$20b9o$24bo$20b3o3b3o$22bo3bo$$21b3ob3o$21b3ob3o$20bob2ob2obo$20b\n3o3b3o$21bo5bo!\n"
         );
 
-        let points = parse_fle_file(contents);
+        let points = parse_fle_file(contents).unwrap();
         let tree = TreeData::gather_all_points(&points);
         let out_points = tree.dump_all_points();
         let rle_tot_str = write_rle(&out_points);
         assert_eq!(expected, rle_tot_str);
     }
     
+    #[test]
+    fn test_load_at_position() {
+        let contents = concat!(
+            "#C glider placed away from the origin\n",
+            "#CXRLE Pos=-1000,77 Gen=400\n",
+            "x = 3, y = 3, rule = B3/S23\n",
+            "bo$2bo$3o!\n"
+        );
+        let pattern = parse_rle(contents).unwrap();
+        let mut tree = TreeData::from_pattern(&pattern);
+        assert_eq!(tree.get_age(), 400);
+        assert!(points_equal(&tree.dump_all_points(), &pattern.absolute_points()));
+
+        // a glider moves one cell down and right every 4 generations
+        tree.step_forward(4);
+        let stepped = tree.to_pattern(&pattern.metadata);
+        assert_eq!(stepped.metadata.origin, Some(Point{x:-999, y:78}));
+        assert_eq!(stepped.metadata.generation, 404);
+        assert_eq!(stepped.metadata.comments, pattern.metadata.comments);
+        assert!(points_equal(&stepped.points, &pattern.points));
+    }
+    
     #[test]
     fn test_serailize_deserialize() {
         let contents = concat!(
@@ -163,10 +235,10 @@ mod tests {
             "12bo8bo$bo2bo2b2o2bo25bo2b2o2bo2bo$6bo5bo7b3o3b3o7bo5bo$6bo5bo8bo5bo8b\no5bo$6bo5bo8b7o8bo5bo$bo2bo2b2o2bo2b2o4bo7bo4b2o2bo2b2o2bo2bo$o8bo3b2o\n4b11o4b2o3bo8bo$o3bo9b2o17b2o9bo3bo$4o11b19o11b4o$16bobo11bobo$19b11o$\n19bo9bo$20b9o$24bo$20b3o3b3o$22bo3bo$$21b3ob3o$21b3ob3o$20bob2ob2obo$20b\n3o3b3o$21bo5bo!\n"
         );
 
-        let points = parse_fle_file(contents);
+        let points = parse_fle_file(contents).unwrap();
         let oldtree = TreeData::gather_all_points(&points);
         let treeser = oldtree.serialize_treerepr();
-        let newtree = TreeData::deserialize_treerepr(&treeser[..]);
+        let newtree = TreeData::deserialize_treerepr(&treeser[..]).unwrap();
         let out_points = newtree.dump_all_points();
         
         let rle_tot_str = write_rle(&out_points);
@@ -176,10 +248,10 @@ mod tests {
     #[test]
     fn test_quadtree_against_gold() {
         let contents = concat!(
-            "x = 12, y = 8, rule = B3/S23\n",
+            "x = 49, y = 22, rule = B3/S23\n",
             "12bo8bo$bo2bo2b2o2bo25bo2b2o2bo2bo$6bo5bo7b3o3b3o7bo5bo$6bo5bo8bo5bo8bo5bo$6bo5bo8b7o8bo5bo$bo2bo2b2o2bo2b2o4bo7bo4b2o2bo2b2o2bo2bo$o8bo3b2o4b11o4b2o3bo8bo$o3bo9b2o17b2o9bo3bo$4o11b19o11b4o$16bobo11bobo$19b11o$19bo9bo$20b9o$24bo$20b3o3b3o$22bo3bo$$21b3ob3o$21b3ob3o$20bob2ob2obo$20b3o3b3o$21bo5bo!\n"
         );
-        let points = parse_fle_file(contents);
+        let points = parse_fle_file(contents).unwrap();
         let n_steps = 5;
         let actual_points = step_forward_actual(&points, n_steps);
         // println!("done with actual");
@@ -198,10 +270,10 @@ mod tests {
     #[test]
     fn test_grayscale_map() {
         let contents = concat!(
-            "x = 12, y = 8, rule = B3/S23\n",
+            "x = 49, y = 22, rule = B3/S23\n",
             "12bo8bo$bo2bo2b2o2bo25bo2b2o2bo2bo$6bo5bo7b3o3b3o7bo5bo$6bo5bo8bo5bo8bo5bo$6bo5bo8b7o8bo5bo$bo2bo2b2o2bo2b2o4bo7bo4b2o2bo2b2o2bo2bo$o8bo3b2o4b11o4b2o3bo8bo$o3bo9b2o17b2o9bo3bo$4o11b19o11b4o$16bobo11bobo$19b11o$19bo9bo$20b9o$24bo$20b3o3b3o$22bo3bo$$21b3ob3o$21b3ob3o$20bob2ob2obo$20b3o3b3o$21bo5bo!\n"
         );
-        let points = parse_fle_file(contents);
+        let points = parse_fle_file(contents).unwrap();
         let tree = TreeData::gather_all_points(&points);
         // let xsize = 4;
         // let ysize = 4;
@@ -0,0 +1,180 @@
+/*
+Life 1.05 and Life 1.06 formats.
+
+Life 1.06 is a plain list of live cell coordinates. Life 1.05 holds
+`#D` description lines, the rule (`#N` for normal Life or `#R`) and
+blocks of `.`/`*` rows, each placed by a preceding `#P x y` line.
+*/
+
+use crate::cells::{grid_rows, parse_grid_line};
+use crate::parse_error::{ParseError, ParseErrorKind};
+use crate::point::Point;
+use crate::rle::{Pattern, PatternMetadata};
+
+pub const LIFE_105_HEADER: &str = "#Life 1.05";
+pub const LIFE_106_HEADER: &str = "#Life 1.06";
+
+fn parse_pair(text: &str) -> Option<Point>{
+    let coords: Vec<Option<i64>> = text.split_whitespace().map(|v|v.parse().ok()).collect();
+    match coords[..]{
+        [Some(x), Some(y)] => Some(Point{x, y}),
+        _ => None,
+    }
+}
+fn read_description(line: &str, metadata: &mut PatternMetadata){
+    let text = line.strip_prefix(' ').unwrap_or(line);
+    if let Some(name) = text.strip_prefix("Name:"){
+        metadata.name = Some(name.trim().to_string());
+    }
+    else if let Some(author) = text.strip_prefix("Author:"){
+        metadata.author = Some(author.trim().to_string());
+    }
+    else{
+        metadata.comments.push(text.to_string());
+    }
+}
+fn write_description(metadata: &PatternMetadata, s: &mut String){
+    if let Some(name) = &metadata.name{
+        s.push_str(format!("#D Name: {}\n", name).as_str());
+    }
+    if let Some(author) = &metadata.author{
+        s.push_str(format!("#D Author: {}\n", author).as_str());
+    }
+    for comment in metadata.comments.iter(){
+        s.push_str(format!("#D {}\n", comment).as_str());
+    }
+}
+
+pub fn parse_life105(file_contents: &str) -> Result<Pattern, ParseError>{
+    let mut metadata = PatternMetadata::default();
+    let mut points: Vec<Point> = Vec::new();
+    let mut block_origin = Point{x:0, y:0};
+    let mut row = 0;
+    for (i, line) in file_contents.lines().enumerate(){
+        let line = line.trim_end();
+        if line.starts_with(LIFE_105_HEADER) || line.is_empty(){
+            continue;
+        }
+        if let Some(description) = line.strip_prefix("#D"){
+            read_description(description, &mut metadata);
+        }
+        else if line == "#N"{
+            metadata.rule = Some("B3/S23".to_string());
+        }
+        else if let Some(rule) = line.strip_prefix("#R"){
+            metadata.rule = Some(rule.trim().to_string());
+        }
+        else if let Some(position) = line.strip_prefix("#P"){
+            block_origin = parse_pair(position).ok_or(ParseError::new(
+                i+1, 1, ParseErrorKind::InvalidMetadata("expected `#P <x> <y>`".to_string())
+            ))?;
+            row = 0;
+        }
+        else if line.starts_with('#'){
+            metadata.extra_lines.push(line.to_string());
+        }
+        else{
+            parse_grid_line(line, i+1, row, &mut|p|points.push(p + block_origin))?;
+            row += 1;
+        }
+    }
+    Ok(Pattern::from_absolute_points(&points, &metadata))
+}
+
+pub fn write_life105(pattern: &Pattern) -> String{
+    let mut s = String::from(LIFE_105_HEADER);
+    s.push('\n');
+    write_description(&pattern.metadata, &mut s);
+    match pattern.metadata.rule.as_deref(){
+        None | Some("B3/S23") => s.push_str("#N\n"),
+        Some(rule) => s.push_str(format!("#R {}\n", rule).as_str()),
+    }
+    let points = pattern.absolute_points();
+    if let (Some(minx), Some(miny)) = (points.iter().map(|p|p.x).min(), points.iter().map(|p|p.y).min()){
+        s.push_str(format!("#P {} {}\n", minx, miny).as_str());
+    }
+    for row in grid_rows(&points, '*', '.'){
+        s.push_str(row.as_str());
+        s.push('\n');
+    }
+    s
+}
+
+pub fn parse_life106(file_contents: &str) -> Result<Pattern, ParseError>{
+    let mut metadata = PatternMetadata::default();
+    let mut points: Vec<Point> = Vec::new();
+    for (i, line) in file_contents.lines().enumerate(){
+        let line = line.trim();
+        if line.starts_with(LIFE_106_HEADER) || line.is_empty(){
+            continue;
+        }
+        if let Some(description) = line.strip_prefix("#D"){
+            read_description(description, &mut metadata);
+        }
+        else if line.starts_with('#'){
+            metadata.extra_lines.push(line.to_string());
+        }
+        else{
+            points.push(parse_pair(line).ok_or(ParseError::new(
+                i+1, 1, ParseErrorKind::InvalidCoordinates(line.to_string())
+            ))?);
+        }
+    }
+    Ok(Pattern::from_absolute_points(&points, &metadata))
+}
+
+pub fn write_life106(pattern: &Pattern) -> String{
+    let mut s = String::from(LIFE_106_HEADER);
+    s.push('\n');
+    write_description(&pattern.metadata, &mut s);
+    let mut points = pattern.absolute_points();
+    points.sort();
+    for p in points.iter(){
+        s.push_str(format!("{} {}\n", p.x, p.y).as_str());
+    }
+    s
+}
+
+#[cfg(test)]
+mod tests {
+    // Note this useful idiom: importing names from outer (for mod tests) scope.
+    use super::*;
+
+    fn sorted(points: Vec<Point>) -> Vec<Point>{
+        let mut res = points;
+        res.sort();
+        res
+    }
+    fn glider() -> Vec<Point>{
+        vec![Point{x:0, y:-1}, Point{x:1, y:0}, Point{x:-1, y:1}, Point{x:0, y:1}, Point{x:1, y:1}]
+    }
+    #[test]
+    fn test_life105() {
+        let contents = concat!(
+            "#Life 1.05\n",
+            "#D Glider\n",
+            "#N\n",
+            "#P -1 -1\n",
+            ".*\n",
+            "..*\n",
+            "***\n",
+        );
+        let pattern = parse_life105(contents).unwrap();
+        assert_eq!(sorted(pattern.absolute_points()), sorted(glider()));
+        assert_eq!(pattern.metadata.comments, vec!["Glider".to_string()]);
+        assert_eq!(write_life105(&pattern), contents);
+
+        let err = parse_life105("#Life 1.05\n#P 1\n").err().unwrap();
+        assert_eq!(err.line, 2);
+    }
+    #[test]
+    fn test_life106() {
+        let contents = "#Life 1.06\n0 -1\n1 0\n-1 1\n0 1\n1 1\n";
+        let pattern = parse_life106(contents).unwrap();
+        assert_eq!(sorted(pattern.absolute_points()), sorted(glider()));
+        assert_eq!(parse_life106(&write_life106(&pattern)).unwrap(), pattern);
+
+        let err = parse_life106("#Life 1.06\n0 1\n0 x\n").err().unwrap();
+        assert_eq!(err, ParseError::new(3, 1, ParseErrorKind::InvalidCoordinates("0 x".to_string())));
+    }
+}
@@ -0,0 +1,285 @@
+/*
+Reading and writing of Golly's macrocell (.mc) format.
+
+A macrocell file lists the nodes of the hashlife DAG bottom up,
+one per line, the root being the last one. Leaves are 8x8 blocks
+written as `.`/`*` rows separated by `$`, which map directly onto
+our raw u64 leaves. Internal nodes are written as
+`<level> <nw> <ne> <sw> <se>`, where level is log2 of the node's
+width and the children are 1-based line numbers, 0 meaning empty.
+Golly centres the root on the origin.
+*/
+
+use std::collections::HashMap;
+
+use crate::frame::{node_size, WindowCache};
+use crate::parse_error::{ParseError, ParseErrorKind};
+use crate::point::Point;
+use crate::quadtree::TreeData;
+
+// a raw 8x8 leaf is a level 3 node in macrocell terms
+const LEAF_LEVEL: u64 = 3;
+
+fn leaf_line(leaf: u64) -> String{
+    let rows: Vec<String> = (0..8).map(|y|{
+        let row = (leaf >> (8*y)) & 0xff;
+        (0..(64 - row.leading_zeros() as u64)).map(|x|if row & (1<<x) != 0 {'*'} else {'.'}).collect()
+    }).collect();
+    // rows are terminated by '$', empty rows at the end can be left out
+    let mut line = rows.join("$");
+    line.truncate(line.trim_end_matches('$').len());
+    line
+}
+fn parse_leaf(line: &str, line_no: usize) -> Result<u64, ParseError>{
+    let mut leaf: u64 = 0;
+    let mut x = 0;
+    let mut y = 0;
+    for (i, c) in line.chars().enumerate(){
+        match c{
+            '.' => x += 1,
+            '*' => {
+                if x >= 8 || y >= 8{
+                    return Err(ParseError::new(line_no, i+1, ParseErrorKind::InvalidNode("leaf is larger than 8x8".to_string())));
+                }
+                leaf |= 1 << (y*8 + x);
+                x += 1;
+            },
+            '$' => {
+                x = 0;
+                y += 1;
+            },
+            c => return Err(ParseError::new(line_no, i+1, ParseErrorKind::UnknownState(c))),
+        }
+    }
+    Ok(leaf)
+}
+fn is_life_rule(rule: &str) -> bool{
+    let rule = rule.trim().to_ascii_uppercase();
+    rule == "B3/S23" || rule == "23/3"
+}
+
+impl TreeData{
+    fn write_macrocell_node(&self, key: u128, depth: u64, indices: &mut HashMap<u128, usize>, lines: &mut Vec<String>) -> usize{
+        if self.is_empty_node(key, depth){
+            return 0;
+        }
+        if let Some(idx) = indices.get(&key){
+            return *idx;
+        }
+        let line = if depth == 0{
+            leaf_line(key as u64)
+        }
+        else{
+            let children = self.children(key).map(|child|self.write_macrocell_node(child, depth-1, indices, lines));
+            format!("{} {} {} {} {}", depth + LEAF_LEVEL, children[0], children[1], children[2], children[3])
+        };
+        lines.push(line);
+        indices.insert(key, lines.len());
+        lines.len()
+    }
+    pub fn write_macrocell(&self) -> String{
+        let mut out = String::from("[M2] (hashlife-rust)\n#R B3/S23\n");
+        if self.age != 0{
+            out.push_str(format!("#G {}\n", self.age).as_str());
+        }
+        // Golly puts the centre of the root on the origin, so the tree is
+        // re-framed on a copy that is free to grow
+        let mut tree = self.pruned_tree();
+        let mut depth = 1;
+        if let Some((min, max)) = tree.bounding_box(){
+            while min.x < -node_size(depth)/2 || min.y < -node_size(depth)/2
+                || max.x >= node_size(depth)/2 || max.y >= node_size(depth)/2{
+                depth += 1;
+            }
+        }
+        let corner = -node_size(depth)/2;
+        let root = tree.frame_key(Point{x: corner, y: corner}, depth, &mut WindowCache::new());
+        let mut lines: Vec<String> = Vec::new();
+        if tree.write_macrocell_node(root, depth, &mut HashMap::new(), &mut lines) == 0{
+            // an empty universe still needs a root node
+            lines.push(format!("{} 0 0 0 0", depth + LEAF_LEVEL));
+        }
+        for line in lines.iter(){
+            out.push_str(line);
+            out.push('\n');
+        }
+        out
+    }
+    pub fn read_macrocell(contents: &str) -> Result<TreeData, ParseError>{
+        let mut tree = TreeData::new();
+        let mut age = 0;
+        // (key, level) of every node line, index 0 stands for the empty node
+        let mut nodes: Vec<(u128, u64)> = vec![(0, 0)];
+        let mut lines = contents.lines().enumerate();
+        match lines.next(){
+            Some((_, header)) if header.starts_with("[M2]") => {},
+            _ => return Err(ParseError::new(1, 1, ParseErrorKind::InvalidHeader("expected a `[M2]` macrocell header".to_string()))),
+        }
+        for (i, line) in lines{
+            let line_no = i+1;
+            let line = line.trim_end();
+            if line.is_empty(){
+                continue;
+            }
+            if let Some(rule) = line.strip_prefix("#R"){
+                if !is_life_rule(rule){
+                    return Err(ParseError::new(line_no, 1, ParseErrorKind::UnsupportedRule(rule.trim().to_string())));
+                }
+            }
+            else if let Some(gen) = line.strip_prefix("#G"){
+                age = gen.trim().parse().map_err(|_|ParseError::new(
+                    line_no, 1, ParseErrorKind::InvalidMetadata("expected `#G <generation>`".to_string())
+                ))?;
+            }
+            else if line.starts_with('#'){
+                // comments and other metadata are not kept
+            }
+            else if line.starts_with(['.', '*', '$']){
+                nodes.push((parse_leaf(line, line_no)? as u128, LEAF_LEVEL));
+            }
+            else{
+                let invalid = |msg: &str| ParseError::new(line_no, 1, ParseErrorKind::InvalidNode(msg.to_string()));
+                let fields: Vec<u64> = line.split_whitespace()
+                    .map(|v|v.parse::<u64>())
+                    .collect::<Result<Vec<u64>, _>>()
+                    .map_err(|_|invalid("expected `<level> <nw> <ne> <sw> <se>`"))?;
+                if fields.len() != 5{
+                    return Err(invalid("expected `<level> <nw> <ne> <sw> <se>`"));
+                }
+                let level = fields[0];
+                if level <= LEAF_LEVEL || level > 62{
+                    return Err(invalid("only 8x8 leaves and nodes above them are supported"));
+                }
+                let mut children = [0u128;4];
+                for (child, idx) in children.iter_mut().zip(fields[1..].iter()){
+                    *child = match nodes.get(*idx as usize){
+                        Some(_) if *idx == 0 => if level - 1 == LEAF_LEVEL {0} else {tree.black_key((level - 1 - LEAF_LEVEL) as usize)},
+                        Some((key, child_level)) if *child_level == level - 1 => *key,
+                        Some(_) => return Err(invalid("child node has the wrong level")),
+                        None => return Err(invalid("child node is not defined before its parent")),
+                    };
+                }
+                nodes.push((tree.add_array(children), level));
+            }
+        }
+        let (root, level) = *nodes.last().unwrap();
+        if nodes.len() == 1{
+            // no nodes at all, an empty universe
+        }
+        else if level == LEAF_LEVEL{
+            // a lone leaf is too small to be a root, gather its cells instead
+            let points: Vec<Point> = (0..64).filter(|bit|(root >> bit) & 1 != 0)
+                .map(|bit|Point{x: bit % 8 - 4, y: bit / 8 - 4})
+                .collect();
+            tree = TreeData::gather_all_points(&points);
+        }
+        else{
+            let depth = level - LEAF_LEVEL;
+            tree.root = root;
+            tree.depth = depth;
+            tree.offset = Point{x: -node_size(depth)/2, y: -node_size(depth)/2};
+        }
+        tree.age = age;
+        Ok(tree)
+    }
+    // reads a macrocell file that may be gzip compressed
+    pub fn read_macrocell_bytes(data: &[u8]) -> Result<TreeData, ParseError>{
+        if data.starts_with(&[0x1f, 0x8b]){
+            return TreeData::read_macrocell(&decompress(data)?);
+        }
+        let contents = std::str::from_utf8(data).map_err(|err|ParseError::new(
+            1, 1, ParseErrorKind::InvalidHeader(format!("file is not text: {}", err))
+        ))?;
+        TreeData::read_macrocell(contents)
+    }
+    #[cfg(feature = "gzip")]
+    pub fn write_macrocell_gzip(&self) -> Vec<u8>{
+        use std::io::Write;
+        let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+        encoder.write_all(self.write_macrocell().as_bytes()).expect("writing to memory cannot fail");
+        encoder.finish().expect("writing to memory cannot fail")
+    }
+}
+
+#[cfg(feature = "gzip")]
+fn decompress(data: &[u8]) -> Result<String, ParseError>{
+    use std::io::Read;
+    let mut contents = String::new();
+    flate2::read::GzDecoder::new(data).read_to_string(&mut contents)
+        .map_err(|err|ParseError::new(1, 1, ParseErrorKind::Decompression(err.to_string())))?;
+    Ok(contents)
+}
+#[cfg(not(feature = "gzip"))]
+fn decompress(_data: &[u8]) -> Result<String, ParseError>{
+    Err(ParseError::new(1, 1, ParseErrorKind::Decompression("built without the `gzip` feature".to_string())))
+}
+
+#[cfg(test)]
+mod tests {
+    // Note this useful idiom: importing names from outer (for mod tests) scope.
+    use super::*;
+
+    fn sorted(points: Vec<Point>) -> Vec<Point>{
+        let mut res = points;
+        res.sort();
+        res
+    }
+    #[test]
+    fn test_leaf_line() {
+        let glider: u64 = 0b010 | (0b100 << 8) | (0b111 << 16);
+        assert_eq!(leaf_line(glider), ".*$..*$***");
+        assert_eq!(parse_leaf(".*$..*$***", 1).unwrap(), glider);
+        assert!(parse_leaf(".........*", 1).is_err());
+    }
+    #[test]
+    fn test_read_golly_nodes() {
+        let contents = concat!(
+            "[M2] (golly 4.2)\n",
+            "#R B3/S23\n",
+            "#G 20\n",
+            "$$$$$$$*\n",
+            ".*$..*$***$\n",
+            "4 0 1 0 2\n",
+        );
+        let tree = TreeData::read_macrocell(contents).unwrap();
+        // the 16x16 root spans [-8, 8), the first leaf is its top right quadrant
+        let expected = vec![
+            Point{x:0, y:-1},
+            Point{x:1, y:0}, Point{x:2, y:1}, Point{x:0, y:2}, Point{x:1, y:2}, Point{x:2, y:2},
+        ];
+        assert_eq!(tree.get_age(), 20);
+        assert_eq!(sorted(tree.dump_all_points()), sorted(expected));
+    }
+    #[test]
+    fn test_round_trip() {
+        let points = vec![Point{x:-5, y:3}, Point{x:-4, y:3}, Point{x:-3, y:3}, Point{x:90, y:-20}, Point{x:91, y:-20}];
+        let mut tree = TreeData::gather_all_points(&points);
+        tree.step_forward(3);
+        let text = tree.write_macrocell();
+        assert!(text.starts_with("[M2]"));
+        assert!(text.contains("#G 3\n"));
+        let loaded = TreeData::read_macrocell(&text).unwrap();
+        assert_eq!(loaded.get_age(), 3);
+        assert_eq!(sorted(loaded.dump_all_points()), sorted(tree.dump_all_points()));
+    }
+    #[cfg(feature = "gzip")]
+    #[test]
+    fn test_gzip_round_trip() {
+        let points = vec![Point{x:1, y:0}, Point{x:2, y:1}, Point{x:0, y:2}, Point{x:1, y:2}, Point{x:2, y:2}];
+        let tree = TreeData::gather_all_points(&points);
+        let data = tree.write_macrocell_gzip();
+        assert_eq!(&data[..2], &[0x1f, 0x8b]);
+        let loaded = TreeData::read_macrocell_bytes(&data).unwrap();
+        assert_eq!(sorted(loaded.dump_all_points()), sorted(points));
+    }
+    #[test]
+    fn test_errors() {
+        assert!(TreeData::read_macrocell("x = 3, y = 3\n").is_err());
+        let err = TreeData::read_macrocell("[M2]\n#R B36/S23\n").err().unwrap();
+        assert_eq!(err.kind, ParseErrorKind::UnsupportedRule("B36/S23".to_string()));
+        let err = TreeData::read_macrocell("[M2]\n**\n5 1 0 0 0\n").err().unwrap();
+        assert_eq!(err.line, 3);
+        let err = TreeData::read_macrocell("[M2]\n4 0 7 0 0\n").err().unwrap();
+        assert_eq!(err.line, 2);
+    }
+}
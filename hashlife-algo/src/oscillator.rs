@@ -0,0 +1,150 @@
+/*
+Cell-level statistics of an oscillator over one period.
+
+The stator holds the cells alive in every phase and the rotor the other
+cells alive in some phase. Heat is the average number of cells changing
+state per generation, read off the XOR of each phase with the next.
+Volatility is the share of the cells ever alive that are in the rotor, and
+strict volatility the share that oscillate at the full period: a cell has
+a shorter period exactly when it repeats after p/q generations for some
+prime q dividing p, which the XOR of the phases p/q apart shows.
+*/
+
+use crate::point::Point;
+use crate::quadtree::TreeData;
+
+const NO_OFFSET: Point = Point{x: 0, y: 0};
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct OscillatorStats{
+    pub period: u64,
+    pub min_population: u64,
+    pub max_population: u64,
+    // cells that change state
+    pub rotor: u64,
+    // cells alive in every phase
+    pub stator: u64,
+    pub heat: f64,
+    pub volatility: f64,
+    pub strict_volatility: f64,
+}
+
+fn prime_factors(mut n: u64) -> Vec<u64>{
+    let mut res = Vec::new();
+    let mut q = 2;
+    while q*q <= n{
+        if n.is_multiple_of(q){
+            res.push(q);
+            while n.is_multiple_of(q){
+                n /= q;
+            }
+        }
+        q += 1;
+    }
+    if n > 1{
+        res.push(n);
+    }
+    res
+}
+
+fn fold(trees: &[TreeData], op: fn(&TreeData, &TreeData, Point) -> TreeData) -> TreeData{
+    trees[1..].iter().fold(trees[0].pruned_tree(), |acc, tree|op(&acc, tree, NO_OFFSET))
+}
+
+impl TreeData{
+    // statistics of the oscillator or still life this pattern is, or settles into within
+    // `max_period` generations. None for spaceships and patterns that do not repeat in time.
+    pub fn oscillator_stats(&self, max_period: u64) -> Option<OscillatorStats>{
+        let periodicity = self.pruned_tree().find_period(max_period)?;
+        if periodicity.is_spaceship(){
+            return None;
+        }
+        let period = periodicity.period;
+        let mut tree = self.pruned_tree();
+        if periodicity.start > tree.get_age(){
+            tree.step_forward(periodicity.start - tree.get_age());
+        }
+        let mut phases = vec![tree.pruned_tree()];
+        for _ in 1..period{
+            tree.step_forward(1);
+            phases.push(tree.pruned_tree());
+        }
+        // cells of a phase differing from the phase `shift` generations on
+        let changes = |shift: u64|->Vec<TreeData>{
+            (0..period).map(|t|phases[t as usize].xor(&phases[((t + shift) % period) as usize], NO_OFFSET)).collect()
+        };
+        let ever_alive = fold(&phases, TreeData::union).num_live_cells();
+        let stator = fold(&phases, TreeData::intersection).num_live_cells();
+        let rotor = ever_alive - stator;
+        let heat = changes(1).iter().map(|t|t.num_live_cells()).sum::<u64>() as f64 / period as f64;
+        // cells failing to repeat at each maximal proper divisor of the period
+        let full_period: Vec<TreeData> = prime_factors(period).iter().map(|q|fold(&changes(period / q), TreeData::union)).collect();
+        let strict_rotor = if full_period.is_empty() {0} else {fold(&full_period, TreeData::intersection).num_live_cells()};
+        let share = |cells: u64|if ever_alive == 0 {0.0} else {cells as f64 / ever_alive as f64};
+        Some(OscillatorStats{
+            period,
+            min_population: phases.iter().map(|t|t.num_live_cells()).min().unwrap(),
+            max_population: phases.iter().map(|t|t.num_live_cells()).max().unwrap(),
+            rotor,
+            stator,
+            heat,
+            volatility: share(rotor),
+            strict_volatility: share(strict_rotor),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    // Note this useful idiom: importing names from outer (for mod tests) scope.
+    use super::*;
+    use crate::rle::parse_rle;
+    use std::collections::HashSet;
+
+    fn stats(rle: &str, max_period: u64) -> Option<OscillatorStats>{
+        TreeData::from_pattern(&parse_rle(rle).unwrap()).oscillator_stats(max_period)
+    }
+    #[test]
+    fn test_simple_oscillators() {
+        let blinker = stats("x = 3, y = 1\n3o!\n", 10).unwrap();
+        assert_eq!((blinker.period, blinker.rotor, blinker.stator, blinker.heat), (2, 4, 1, 4.0));
+        assert_eq!((blinker.volatility, blinker.strict_volatility), (0.8, 0.8));
+        let block = stats("x = 2, y = 2\n2o$2o!\n", 10).unwrap();
+        assert_eq!((block.period, block.rotor, block.stator, block.heat, block.volatility), (1, 0, 4, 0.0, 0.0));
+        assert_eq!(stats("x = 3, y = 3\nbo$2bo$3o!\n", 10), None);
+        assert_eq!(prime_factors(60), vec![2, 3, 5]);
+    }
+    // checks the statistics against ones worked out from the cells of every phase
+    fn check_cell_by_cell(rle: &str, period: u64) -> OscillatorStats{
+        let result = stats(rle, period).unwrap();
+        assert_eq!(result.period, period);
+        let p = period as usize;
+        let mut tree = TreeData::from_pattern(&parse_rle(rle).unwrap());
+        let mut phases: Vec<HashSet<Point>> = Vec::new();
+        for _ in 0..p{
+            phases.push(tree.dump_all_points().into_iter().collect());
+            tree.step_forward(1);
+        }
+        let all: HashSet<Point> = phases.iter().flatten().cloned().collect();
+        let stator = all.iter().filter(|c|phases.iter().all(|ph|ph.contains(c))).count() as u64;
+        let heat: usize = (0..p).map(|t|phases[t].symmetric_difference(&phases[(t + 1) % p]).count()).sum();
+        let strict = all.iter().filter(|c|{
+            let states: Vec<bool> = phases.iter().map(|ph|ph.contains(c)).collect();
+            (1..p).all(|d|(0..p).any(|t|states[t] != states[(t + d) % p]))
+        }).count();
+        assert_eq!((result.stator, result.rotor), (stator, all.len() as u64 - stator));
+        assert_eq!(result.heat, heat as f64 / period as f64);
+        assert_eq!(result.strict_volatility, strict as f64 / all.len() as f64);
+        result
+    }
+    #[test]
+    fn test_matches_cell_by_cell() {
+        let pentadecathlon = check_cell_by_cell("x = 10, y = 3\n2bo4bo$2ob4ob2o$2bo4bo!\n", 15);
+        // the published figures
+        assert_eq!((pentadecathlon.rotor, pentadecathlon.stator, pentadecathlon.heat), (80, 0, 22.4));
+        assert_eq!((pentadecathlon.volatility, pentadecathlon.strict_volatility), (1.0, 1.0));
+        // next to a blinker the period is 30, which no single cell has
+        let combined = check_cell_by_cell("x = 10, y = 12\n2bo4bo$2ob4ob2o$2bo4bo8$4b3o!\n", 30);
+        assert_eq!(combined.strict_volatility, 0.0);
+    }
+}
@@ -0,0 +1,71 @@
+use std::fmt;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ParseErrorKind {
+    // a cell state letter other than the ones the parser understands
+    UnknownState(char),
+    // the `x = .., y = ..` line is missing or could not be read
+    InvalidHeader(String),
+    // a recognised `#` line with a malformed value
+    InvalidMetadata(String),
+    // the pattern body ended without the closing `!`
+    MissingTerminator,
+    // the body is larger than the size declared in the header
+    SizeMismatch{declared: (i64, i64), actual: (i64, i64)},
+    // a run count too large to be represented
+    RunCountOverflow,
+    // a macrocell node line that is malformed or refers to a missing node
+    InvalidNode(String),
+    // a rule other than B3/S23, which is the only one the simulator implements
+    UnsupportedRule(String),
+    // compressed input that could not be decompressed
+    Decompression(String),
+    // a Life 1.06 line that is not a pair of integer coordinates
+    InvalidCoordinates(String),
+    // an apgcode with an unknown prefix, a malformed body or the wrong population
+    InvalidApgcode(String),
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ParseError {
+    // line and column are 1-based, like text editors report them
+    pub line: usize,
+    pub column: usize,
+    pub kind: ParseErrorKind,
+}
+
+impl ParseError {
+    pub fn new(line: usize, column: usize, kind: ParseErrorKind) -> ParseError {
+        ParseError { line, column, kind }
+    }
+}
+
+impl fmt::Display for ParseErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ParseErrorKind::UnknownState(c) => write!(f, "unknown cell state '{}'", c.escape_default()),
+            ParseErrorKind::InvalidHeader(msg) => write!(f, "invalid header: {}", msg),
+            ParseErrorKind::InvalidMetadata(msg) => write!(f, "invalid metadata: {}", msg),
+            ParseErrorKind::MissingTerminator => write!(f, "pattern is missing the terminating '!'"),
+            ParseErrorKind::SizeMismatch{declared, actual} => write!(
+                f,
+                "pattern is {}x{} but header declares {}x{}",
+                actual.0, actual.1, declared.0, declared.1
+            ),
+            ParseErrorKind::RunCountOverflow => write!(f, "run count is too large"),
+            ParseErrorKind::InvalidNode(msg) => write!(f, "invalid node: {}", msg),
+            ParseErrorKind::UnsupportedRule(rule) => write!(f, "unsupported rule '{}', only B3/S23 is implemented", rule),
+            ParseErrorKind::Decompression(msg) => write!(f, "could not decompress: {}", msg),
+            ParseErrorKind::InvalidCoordinates(line) => write!(f, "expected `<x> <y>` coordinates, found '{}'", line),
+            ParseErrorKind::InvalidApgcode(msg) => write!(f, "invalid apgcode: {}", msg),
+        }
+    }
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "line {}, column {}: {}", self.line, self.column, self.kind)
+    }
+}
+
+impl std::error::Error for ParseError {}
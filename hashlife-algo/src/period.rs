@@ -0,0 +1,180 @@
+/*
+Detection of oscillators and spaceships.
+
+Each generation is reduced to a translation independent key: the node
+holding the pattern with its bounding box moved to the node's top left
+corner. Since node keys are structural hashes, two generations hold the
+same pattern, up to translation, exactly when these keys are equal.
+*/
+
+use std::collections::HashMap;
+use std::fmt;
+
+use crate::frame::{node_size, WindowCache};
+use crate::point::Point;
+use crate::quadtree::TreeData;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Periodicity{
+    pub period: u64,
+    // how far the pattern moves every period
+    pub displacement: Point,
+    // first generation of the repeating cycle
+    pub start: u64,
+}
+
+fn gcd(a: u64, b: u64) -> u64{
+    if b == 0 {a} else {gcd(b, a % b)}
+}
+fn speed_fraction(cells: u64, period: u64) -> String{
+    let div = gcd(cells, period);
+    let (num, den) = (cells / div, period / div);
+    match (num, den){
+        (1, 1) => "c".to_string(),
+        (1, den) => format!("c/{}", den),
+        (num, 1) => format!("{}c", num),
+        (num, den) => format!("{}c/{}", num, den),
+    }
+}
+
+impl Periodicity{
+    pub fn is_still_life(&self) -> bool{
+        self.period == 1 && !self.is_spaceship()
+    }
+    pub fn is_oscillator(&self) -> bool{
+        self.period > 1 && !self.is_spaceship()
+    }
+    pub fn is_spaceship(&self) -> bool{
+        self.displacement != Point{x: 0, y: 0}
+    }
+    // speed in the usual notation, e.g. "c/4 diagonal" for the glider
+    // or "(2,1)c/6" for knightships, None if the pattern does not move
+    pub fn speed(&self) -> Option<String>{
+        let dx = self.displacement.x.unsigned_abs();
+        let dy = self.displacement.y.unsigned_abs();
+        if dx == 0 && dy == 0{
+            None
+        }
+        else if dx == 0 || dy == 0{
+            Some(format!("{} orthogonal", speed_fraction(dx.max(dy), self.period)))
+        }
+        else if dx == dy{
+            Some(format!("{} diagonal", speed_fraction(dx, self.period)))
+        }
+        else{
+            Some(format!("({},{})c/{}", dx.max(dy), dx.min(dy), self.period))
+        }
+    }
+}
+
+impl fmt::Display for Periodicity{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result{
+        match self.speed(){
+            Some(speed) => write!(f, "{} spaceship", speed),
+            None if self.period == 1 => write!(f, "still life"),
+            None => write!(f, "period {} oscillator", self.period),
+        }
+    }
+}
+
+// remembers the normalised key of every generation it is shown
+#[derive(Default)]
+pub struct PeriodDetector{
+    seen: HashMap<(u128, u64), (u64, Point)>,
+}
+impl PeriodDetector{
+    pub fn new() -> PeriodDetector{
+        PeriodDetector::default()
+    }
+    // records the tree's current generation, returning the periodicity once a
+    // generation repeats. Generations have to be recorded one at a time for the
+    // period to be exact, and the tree must not be pruned in between.
+    pub fn record(&mut self, tree: &mut TreeData) -> Option<Periodicity>{
+        let (key, depth, corner) = tree.normalized_root()?;
+        let age = tree.get_age();
+        match self.seen.get(&(key, depth)){
+            Some((start, start_corner)) if *start < age => Some(Periodicity{
+                period: age - start,
+                displacement: corner + start_corner.neg(),
+                start: *start,
+            }),
+            Some(_) => None,
+            None => {
+                self.seen.insert((key, depth), (age, corner));
+                None
+            }
+        }
+    }
+}
+
+impl TreeData{
+    // key and depth of the smallest node holding the pattern with its bounding
+    // box at the node's top left, and the top left corner of the bounding box
+    pub(crate) fn normalized_root(&mut self) -> Option<(u128, u64, Point)>{
+        let (min, max) = self.bounding_box()?;
+        let extent = std::cmp::max(max.x - min.x, max.y - min.y) + 1;
+        let mut depth = 0;
+        while node_size(depth) < extent{
+            depth += 1;
+        }
+        let mut cache = WindowCache::new();
+        let key = self.frame_key(min, depth, &mut cache);
+        Some((key, depth, min))
+    }
+    // steps the tree one generation at a time until it repeats, or until
+    // `max_generations` have passed. The tree is left at the generation where
+    // the repeat was found. Returns None for patterns that die out.
+    pub fn find_period(&mut self, max_generations: u64) -> Option<Periodicity>{
+        let mut detector = PeriodDetector::new();
+        for gen in 0..=max_generations{
+            if gen != 0{
+                self.step_forward(1);
+            }
+            if let Some(periodicity) = detector.record(self){
+                return Some(periodicity);
+            }
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    // Note this useful idiom: importing names from outer (for mod tests) scope.
+    use super::*;
+    use crate::rle::parse_rle;
+
+    fn find_period(rle: &str) -> Option<Periodicity>{
+        let mut tree = TreeData::from_pattern(&parse_rle(rle).unwrap());
+        tree.find_period(100)
+    }
+    #[test]
+    fn test_find_period() {
+        let block = find_period("x = 2, y = 2\n2o$2o!\n").unwrap();
+        assert!(block.is_still_life());
+        assert_eq!(block.to_string(), "still life");
+        let blinker = find_period("x = 3, y = 1\n3o!\n").unwrap();
+        assert_eq!((blinker.period, blinker.displacement), (2, Point{x:0, y:0}));
+        assert_eq!(blinker.to_string(), "period 2 oscillator");
+        let glider = find_period("x = 3, y = 3\nbo$2bo$3o!\n").unwrap();
+        assert_eq!((glider.period, glider.displacement, glider.start), (4, Point{x:1, y:1}, 0));
+        assert_eq!(glider.to_string(), "c/4 diagonal spaceship");
+        let lwss = find_period("x = 5, y = 4\nbo2bo$o4b$o3bo$4o!\n").unwrap();
+        assert_eq!(lwss.period, 4);
+        assert_eq!(lwss.displacement.x.abs() + lwss.displacement.y.abs(), 2);
+        assert_eq!(lwss.speed().unwrap(), "c/2 orthogonal");
+        // becomes a block after one generation
+        let tromino = find_period("x = 2, y = 2\n2o$o!\n").unwrap();
+        assert_eq!((tromino.period, tromino.start), (1, 1));
+        assert_eq!(find_period("x = 2, y = 1\n2o!\n"), None);
+    }
+    #[test]
+    fn test_speed_notation() {
+        let knight = Periodicity{period: 6, displacement: Point{x:-1, y:2}, start: 0};
+        assert_eq!(knight.speed().unwrap(), "(2,1)c/6");
+        let copperhead = Periodicity{period: 10, displacement: Point{x:0, y:-1}, start: 0};
+        assert_eq!(copperhead.speed().unwrap(), "c/10 orthogonal");
+        let fast = Periodicity{period: 5, displacement: Point{x:2, y:0}, start: 0};
+        assert_eq!(fast.speed().unwrap(), "2c/5 orthogonal");
+    }
+}
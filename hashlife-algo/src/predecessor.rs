@@ -0,0 +1,270 @@
+/*
+Predecessor search: finds a pattern inside a bounding region that becomes
+the target after a given number of generations, or shows that the region
+holds none, which for a single generation and a large enough margin is how
+Gardens of Eden are proven.
+
+Each cell of each generation is a boolean variable, the region growing by
+one cell per generation since nothing can be born further out. The rule is
+turned into clauses from the same transition the stepping code uses: runs
+of neighbour counts with the same outcome for dead and live cells become
+one "at least" or "at most" clause per subset of neighbours, and the other
+counts get a clause per exact arrangement. The last generation is fixed to
+the target, and the clauses go to the embedded SAT solver.
+*/
+
+use std::collections::HashSet;
+
+use crate::point::Point;
+use crate::quadtree::TreeData;
+use crate::raw_ops::next_cell_state;
+use crate::sat::{neg, pos, Lit, SatResult, Solver};
+
+#[derive(Clone, Debug)]
+pub struct PredecessorOptions{
+    pub generations: u64,
+    // cells around the bounding box of the target the predecessor may use
+    pub margin: i64,
+    // live cells the predecessor may have, to look for small ones
+    pub max_population: Option<u64>,
+    // gives up after this many solver conflicts
+    pub max_conflicts: Option<u64>,
+}
+
+impl Default for PredecessorOptions{
+    fn default() -> Self{
+        PredecessorOptions{
+            generations: 1,
+            margin: 2,
+            max_population: None,
+            max_conflicts: None,
+        }
+    }
+}
+
+pub enum Predecessor{
+    Found(TreeData),
+    // no pattern inside the region evolves into the target
+    NoneInRegion,
+    GaveUp,
+}
+
+#[derive(Copy, Clone)]
+enum Cell{
+    Fixed(bool),
+    Var(u32),
+}
+
+// the cells of one generation over a rectangle, dead outside it
+struct Layer{
+    min: Point,
+    width: i64,
+    height: i64,
+    cells: Vec<Cell>,
+}
+
+impl Layer{
+    fn new(min: Point, max: Point, mut cell: impl FnMut(Point) -> Cell) -> Layer{
+        let (width, height) = (max.x - min.x + 1, max.y - min.y + 1);
+        let cells = (0..height).flat_map(|y|(0..width).map(move |x|Point{x, y})).map(|p|cell(min + p)).collect();
+        Layer{min, width, height, cells}
+    }
+    fn get(&self, p: Point) -> Cell{
+        let (x, y) = (p.x - self.min.x, p.y - self.min.y);
+        if x < 0 || y < 0 || x >= self.width || y >= self.height{
+            return Cell::Fixed(false);
+        }
+        self.cells[(y*self.width + x) as usize]
+    }
+    fn points(&self) -> impl Iterator<Item = Point> + '_{
+        (0..self.height).flat_map(move |y|(0..self.width).map(move |x|self.min + Point{x, y}))
+    }
+}
+
+// the rule as clauses over the 8 neighbours and the centre (index 8): if every listed
+// cell has the listed state, the next state of the centre is the one given
+fn rule_clauses() -> Vec<(Vec<(usize, bool)>, bool)>{
+    let uniform = |n: u64|{
+        let dead = next_cell_state(n, false);
+        (dead == next_cell_state(n, true)).then_some(dead)
+    };
+    // counts from `high` up have the same outcome, as do counts below `low`
+    let mut high = 9;
+    while high > 0 && uniform(high - 1).is_some() && (high == 9 || uniform(high - 1) == uniform(high)){
+        high -= 1;
+    }
+    let mut low = 0;
+    while low < high && uniform(low).is_some() && (low == 0 || uniform(low) == uniform(low - 1)){
+        low += 1;
+    }
+    let mut res = Vec::new();
+    for mask in 0..256u32{
+        let count = mask.count_ones() as u64;
+        // the neighbours in `mask` alive, and with `exact` the others dead
+        let neighbours = |exact: bool|-> Vec<(usize, bool)>{
+            (0..8).filter(|i|exact || mask >> i & 1 == 1).map(|i|(i, mask >> i & 1 == 1)).collect()
+        };
+        if count == high{
+            res.push((neighbours(false), uniform(high).unwrap()));
+        }
+        // at most `low - 1` alive means some `9 - low` are dead
+        if low > 0 && count == 9 - low{
+            let dead: Vec<(usize, bool)> = (0..8).filter(|i|mask >> i & 1 == 1).map(|i|(i, false)).collect();
+            res.push((dead, uniform(low - 1).unwrap()));
+        }
+        if count >= low && count < high{
+            match uniform(count){
+                Some(state) => res.push((neighbours(true), state)),
+                None => for alive in [false, true]{
+                    let mut cells = neighbours(true);
+                    cells.push((8, alive));
+                    res.push((cells, next_cell_state(count, alive)));
+                },
+            }
+        }
+    }
+    res
+}
+
+// adds the clause "one of the cells has the given state", skipping it when a fixed cell already does
+fn add_clause(solver: &mut Solver, cells: impl Iterator<Item = (Cell, bool)>){
+    let mut lits: Vec<Lit> = Vec::new();
+    for (cell, state) in cells{
+        match cell{
+            Cell::Fixed(value) if value == state => return,
+            Cell::Fixed(_) => {},
+            Cell::Var(var) => lits.push(if state {pos(var)} else {neg(var)}),
+        }
+    }
+    solver.add_clause(&lits);
+}
+
+impl TreeData{
+    // searches for a pattern within `options.margin` cells of the bounding box of this one
+    // that becomes exactly this pattern after `options.generations` generations
+    pub fn find_predecessor(&self, options: &PredecessorOptions) -> Predecessor{
+        assert!(options.generations > 0 && options.margin >= 0);
+        let Some((min, max)) = self.bounding_box() else {
+            return Predecessor::Found(TreeData::new());
+        };
+        let target: HashSet<Point> = self.dump_all_points().into_iter().collect();
+        let mut solver = Solver::new();
+        let grow = |t: i64|(min + Point{x: -options.margin - t, y: -options.margin - t}, max + Point{x: options.margin + t, y: options.margin + t});
+        let (lo, hi) = grow(0);
+        let mut layers = vec![Layer::new(lo, hi, |_|Cell::Var(solver.new_var()))];
+        if let Some(max) = options.max_population{
+            let vars: Vec<u32> = layers[0].cells.iter().filter_map(|c|match c{Cell::Var(var) => Some(*var), Cell::Fixed(_) => None}).collect();
+            solver.add_at_most(&vars, max as usize);
+        }
+        let rule = rule_clauses();
+        let offsets = [(-1, -1), (0, -1), (1, -1), (-1, 0), (1, 0), (-1, 1), (0, 1), (1, 1), (0, 0)];
+        for t in 1..=options.generations as i64{
+            let (lo, hi) = grow(t);
+            let last = t == options.generations as i64;
+            let layer = Layer::new(lo, hi, |p|if last {Cell::Fixed(target.contains(&p))} else {Cell::Var(solver.new_var())});
+            let prev = layers.last().unwrap();
+            for p in layer.points(){
+                let around: Vec<Cell> = offsets.iter().map(|(dx, dy)|prev.get(p + Point{x: *dx, y: *dy})).collect();
+                let next = layer.get(p);
+                for (cells, state) in rule.iter(){
+                    let conditions = cells.iter().map(|(i, s)|(around[*i], !s));
+                    add_clause(&mut solver, conditions.chain(std::iter::once((next, *state))));
+                }
+            }
+            layers.push(layer);
+        }
+        let initial = &layers[0];
+        match solver.solve(options.max_conflicts){
+            SatResult::Sat(model) => {
+                let points: Vec<Point> = initial.points().filter(|p|match initial.get(*p){
+                    Cell::Var(var) => model[var as usize],
+                    Cell::Fixed(value) => value,
+                }).collect();
+                let mut tree = TreeData::gather_all_points(&points);
+                tree.age = self.age.saturating_sub(options.generations);
+                Predecessor::Found(tree)
+            },
+            SatResult::Unsat => Predecessor::NoneInRegion,
+            SatResult::Unknown => Predecessor::GaveUp,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    // Note this useful idiom: importing names from outer (for mod tests) scope.
+    use super::*;
+    use crate::rle::parse_rle;
+
+    fn sorted(tree: &TreeData) -> Vec<Point>{
+        let mut points = tree.dump_all_points();
+        points.sort();
+        points
+    }
+    // checks that a found predecessor steps forward into the target
+    fn check(rle: &str, generations: u64, margin: i64) -> bool{
+        let target = TreeData::from_pattern(&parse_rle(rle).unwrap());
+        let options = PredecessorOptions{generations, margin, ..PredecessorOptions::default()};
+        match target.find_predecessor(&options){
+            Predecessor::Found(mut tree) => {
+                let (lo, hi) = target.bounding_box().unwrap();
+                let (plo, phi) = tree.bounding_box().unwrap();
+                assert!(plo.x >= lo.x - margin && plo.y >= lo.y - margin && phi.x <= hi.x + margin && phi.y <= hi.y + margin);
+                tree.step_forward(generations);
+                assert_eq!(sorted(&tree), sorted(&target));
+                true
+            },
+            Predecessor::NoneInRegion => false,
+            Predecessor::GaveUp => panic!("no conflict limit was set"),
+        }
+    }
+    #[test]
+    fn test_rule_clauses() {
+        // every neighbourhood gets exactly the next state of the rule from the clauses that apply
+        let rule = rule_clauses();
+        assert_eq!(rule.len(), 190);
+        for cells in 0..512u32{
+            let alive = |i: usize|cells >> i & 1 == 1;
+            let expected = next_cell_state((cells & 0xff).count_ones() as u64, alive(8));
+            let forced: Vec<bool> = rule.iter().filter(|(c, _)|c.iter().all(|(i, s)|alive(*i) == *s)).map(|(_, s)|*s).collect();
+            assert!(!forced.is_empty() && forced.iter().all(|s|*s == expected));
+        }
+    }
+    #[test]
+    fn test_single_cell() {
+        // a lone cell needs three cells around it the generation before
+        assert!(!check("x = 1, y = 1\no!\n", 1, 0));
+        assert!(check("x = 1, y = 1\no!\n", 1, 1));
+    }
+    #[test]
+    fn test_glider() {
+        assert!(check("x = 3, y = 3\nbo$2bo$3o!\n", 1, 1));
+        assert!(check("x = 3, y = 3\nbo$2bo$3o!\n", 2, 1));
+        assert!(check("x = 3, y = 1\n3o!\n", 3, 2));
+    }
+    #[test]
+    fn test_max_population() {
+        // the smallest parents of a blinker have three cells
+        let target = TreeData::from_pattern(&parse_rle("x = 3, y = 1\n3o!\n").unwrap());
+        let options = |max|PredecessorOptions{max_population: Some(max), ..PredecessorOptions::default()};
+        assert!(matches!(target.find_predecessor(&options(2)), Predecessor::NoneInRegion));
+        match target.find_predecessor(&options(3)){
+            Predecessor::Found(mut tree) => {
+                assert_eq!(tree.num_live_cells(), 3);
+                tree.step_forward(1);
+                assert_eq!(sorted(&tree), sorted(&target));
+            },
+            _ => panic!("a vertical blinker is a parent"),
+        }
+    }
+    #[test]
+    fn test_empty_and_gave_up() {
+        assert!(matches!(TreeData::new().find_predecessor(&PredecessorOptions::default()), Predecessor::Found(_)));
+        // a lone cell needs three parents, which takes the solver more than one conflict to rule out
+        let target = TreeData::from_pattern(&parse_rle("x = 1, y = 1\no!\n").unwrap());
+        let options = PredecessorOptions{margin: 1, max_population: Some(2), max_conflicts: Some(1), ..PredecessorOptions::default()};
+        assert!(matches!(target.find_predecessor(&options), Predecessor::GaveUp));
+        let options = PredecessorOptions{max_conflicts: None, ..options};
+        assert!(matches!(target.find_predecessor(&options), Predecessor::NoneInRegion));
+    }
+}
@@ -0,0 +1,230 @@
+/*
+Probes: cells or small regions whose population is recorded while the
+universe runs, e.g. to read off the gliders a machine emits.
+
+Cells can only be affected by cells at most one step away per generation,
+so the contents of a region `k` generations on only depend on the region
+grown by `k` cells today. When samples are wanted more often than every
+LOCAL_STEPS generations, each probe gets a small universe cut out of that
+grown region and run one sample at a time, while the real universe jumps
+the whole LOCAL_STEPS at once.
+*/
+
+use crate::frame::{node_size, WindowCache};
+use crate::point::Point;
+use crate::quadtree::TreeData;
+
+// generations covered by each cut out universe
+const LOCAL_STEPS: u64 = 64;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Probe{
+    pub top_left: Point,
+    pub width: i64,
+    pub height: i64,
+}
+impl Probe{
+    pub fn cell(p: Point) -> Probe{
+        Probe{top_left: p, width: 1, height: 1}
+    }
+    pub fn region(top_left: Point, width: i64, height: i64) -> Probe{
+        Probe{top_left, width, height}
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct ProbeEvent{
+    pub probe: usize,
+    pub generation: u64,
+    // true when the probe went from empty to having live cells, false for the reverse
+    pub live: bool,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ProbeRecording{
+    // generation of every sample
+    pub generations: Vec<u64>,
+    // population of every probe at every sample, indexed by probe first
+    pub populations: Vec<Vec<u64>>,
+}
+impl ProbeRecording{
+    // generations at which probes went live or dead, the first sample counting as live if occupied
+    pub fn events(&self) -> Vec<ProbeEvent>{
+        let mut res = Vec::new();
+        for (probe, pops) in self.populations.iter().enumerate(){
+            let mut was_live = false;
+            for (gen, pop) in self.generations.iter().zip(pops.iter()){
+                if (*pop > 0) != was_live{
+                    was_live = *pop > 0;
+                    res.push(ProbeEvent{probe, generation: *gen, live: was_live});
+                }
+            }
+        }
+        res.sort_by_key(|e|(e.generation, e.probe));
+        res
+    }
+    // one line per sample: the generation, then the population of each probe
+    pub fn to_csv(&self) -> String{
+        let mut s = String::from("generation");
+        for i in 0..self.populations.len(){
+            s.push_str(format!(",probe{}", i).as_str());
+        }
+        s.push('\n');
+        for (i, gen) in self.generations.iter().enumerate(){
+            s.push_str(gen.to_string().as_str());
+            for pops in self.populations.iter(){
+                s.push_str(format!(",{}", pops[i]).as_str());
+            }
+            s.push('\n');
+        }
+        s
+    }
+}
+
+fn leaf_population_in(leaf: u64, min: Point, max: Point) -> u64{
+    let row_mask = ((1u64 << (max.x - min.x)) - 1) << min.x;
+    (min.y..max.y).map(|y|((leaf >> (8*y)) & row_mask).count_ones() as u64).sum()
+}
+
+impl TreeData{
+    // live cells of the node with its top left at `origin` inside [min, max)
+    fn node_population_in(&self, key: u128, depth: u64, origin: Point, min: Point, max: Point) -> u64{
+        let size = node_size(depth);
+        if self.is_empty_node(key, depth)
+            || max.x <= origin.x || max.y <= origin.y || origin.x + size <= min.x || origin.y + size <= min.y{
+            return 0;
+        }
+        if depth == 0{
+            let lo = Point{x: std::cmp::max(min.x - origin.x, 0), y: std::cmp::max(min.y - origin.y, 0)};
+            let hi = Point{x: std::cmp::min(max.x - origin.x, 8), y: std::cmp::min(max.y - origin.y, 8)};
+            return leaf_population_in(key as u64, lo, hi);
+        }
+        if min.x <= origin.x && min.y <= origin.y && origin.x + size <= max.x && origin.y + size <= max.y{
+            return self.map.get(key).unwrap().set_count;
+        }
+        let half = node_size(depth - 1);
+        self.children(key).iter().enumerate().map(|(i, child)|{
+            let shift = Point{x: (i as i64 % 2)*half, y: (i as i64 / 2)*half};
+            self.node_population_in(*child, depth - 1, origin + shift, min, max)
+        }).sum()
+    }
+    // live cells in the rectangle of the given size with its top left at `top_left`
+    pub fn population_in(&self, top_left: Point, width: i64, height: i64) -> u64{
+        let max = top_left + Point{x: width, y: height};
+        self.node_population_in(self.root, self.depth, self.offset, top_left, max)
+    }
+    fn copy_structure(&self, key: u128, depth: u64, into: &mut TreeData){
+        if depth == 0 || into.map.get(key).is_some(){
+            return;
+        }
+        let children = self.children(key);
+        for child in children.iter(){
+            self.copy_structure(*child, depth - 1, into);
+        }
+        into.add_array(children);
+    }
+    // a universe holding only the cells of the probe grown by `margin`
+    fn cut_out(&mut self, probe: &Probe, margin: i64) -> TreeData{
+        let top_left = probe.top_left + Point{x: -margin, y: -margin};
+        let extent = std::cmp::max(probe.width, probe.height) + 2*margin;
+        let mut depth = 1;
+        while node_size(depth) < extent{
+            depth += 1;
+        }
+        let key = self.frame_key(top_left, depth, &mut WindowCache::new());
+        let mut res = TreeData::new();
+        self.copy_structure(key, depth, &mut res);
+        res.root = key;
+        res.depth = depth;
+        res.offset = top_left;
+        res.age = self.age;
+        res
+    }
+    fn sample_probes(&self, probes: &[Probe], recording: &mut ProbeRecording){
+        recording.generations.push(self.age);
+        for (i, probe) in probes.iter().enumerate(){
+            recording.populations[i].push(self.population_in(probe.top_left, probe.width, probe.height));
+        }
+    }
+    // runs the universe for `generations`, sampling the probes every `cadence` generations,
+    // starting with the current one
+    pub fn record_probes(&mut self, probes: &[Probe], generations: u64, cadence: u64) -> ProbeRecording{
+        assert!(cadence > 0);
+        let mut recording = ProbeRecording{generations: Vec::new(), populations: vec![Vec::new(); probes.len()]};
+        let end = self.age + generations;
+        self.sample_probes(probes, &mut recording);
+        if cadence >= LOCAL_STEPS{
+            while self.age + cadence <= end{
+                self.step_forward(cadence);
+                self.sample_probes(probes, &mut recording);
+            }
+        }
+        else{
+            let chunk = LOCAL_STEPS / cadence * cadence;
+            while self.age + cadence <= end{
+                let steps = std::cmp::min(chunk, (end - self.age) / cadence * cadence);
+                let mut local = Vec::with_capacity(probes.len());
+                for probe in probes.iter(){
+                    local.push(self.cut_out(probe, steps as i64));
+                }
+                let start = recording.generations.len();
+                for gen in (cadence..=steps).step_by(cadence as usize){
+                    recording.generations.push(self.age + gen);
+                }
+                for (i, (probe, tree)) in probes.iter().zip(local.iter_mut()).enumerate(){
+                    for _ in recording.generations[start..].iter(){
+                        tree.step_forward(cadence);
+                        recording.populations[i].push(tree.population_in(probe.top_left, probe.width, probe.height));
+                    }
+                }
+                self.step_forward(steps);
+            }
+        }
+        if self.age < end{
+            self.step_forward(end - self.age);
+        }
+        recording
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    // Note this useful idiom: importing names from outer (for mod tests) scope.
+    use super::*;
+    use crate::rle::parse_rle;
+
+    const GOSPER_GUN: &str = "x = 36, y = 9\n24bo$22bobo$12b2o6b2o12b2o$11bo3bo4b2o12b2o$2o8bo5bo3b2o$2o8bo3bob2o4bobo$10bo5bo7bo$11bo3bo$12b2o!\n";
+
+    #[test]
+    fn test_population_in() {
+        let points: Vec<Point> = (0..40).map(|i|Point{x: i*3 - 50, y: i*2 - 30}).collect();
+        let tree = TreeData::gather_all_points(&points);
+        let (min, w, h) = (Point{x: -20, y: -17}, 33, 21);
+        let expected = points.iter().filter(|p|p.x >= min.x && p.x < min.x + w && p.y >= min.y && p.y < min.y + h).count();
+        assert_eq!(tree.population_in(min, w, h), expected as u64);
+        assert_eq!(tree.population_in(Point{x: -1000, y: -1000}, 5000, 5000), 40);
+        assert_eq!(tree.population_in(Point{x: 1000, y: 0}, 5, 5), 0);
+    }
+    #[test]
+    fn test_local_stepping_matches() {
+        // a region in the path of the gun's gliders, a cell in it and a region over the gun itself
+        let probes = [Probe::region(Point{x: 29, y: 16}, 5, 5), Probe::cell(Point{x: 31, y: 18}), Probe::region(Point{x: 0, y: 0}, 36, 9)];
+        let mut tree = TreeData::from_pattern(&parse_rle(GOSPER_GUN).unwrap());
+        let recording = tree.record_probes(&probes, 100, 1);
+        assert_eq!(tree.get_age(), 100);
+        let mut reference = TreeData::from_pattern(&parse_rle(GOSPER_GUN).unwrap());
+        let expected = reference.record_probes(&probes, 100, 64);
+        assert_eq!(recording.generations.len(), 101);
+        for (i, gen) in expected.generations.iter().enumerate(){
+            for p in 0..probes.len(){
+                assert_eq!(recording.populations[p][*gen as usize], expected.populations[p][i]);
+            }
+        }
+        // a glider passes the region every 30 generations, the first arriving at generation 49
+        let pops = &recording.populations[0];
+        assert_eq!(recording.events().iter().find(|e|e.probe == 0), Some(&ProbeEvent{probe: 0, generation: 49, live: true}));
+        assert!(pops[49] > 0 && pops[..49].iter().all(|p|*p == 0));
+        assert!((49..pops.len()-30).all(|g|pops[g] == pops[g+30]));
+        assert_eq!(recording.to_csv().lines().count(), 102);
+    }
+}
@@ -3,28 +3,27 @@ use std::intrinsics::transmute;
 use std::mem::size_of;
 
 use crate::largekey_table::LargeKeyTable;
-use crate::serialize;
 use std::collections::HashMap;
 use std::collections::hash_map::Entry;
 
 use metrohash::MetroHash128;
 use crate::point::Point;
+use crate::rle::{Pattern, PatternMetadata};
 use crate::raw_ops::*;
-use crate::serialize::*;
 
 
 #[derive(Copy, Clone, Default)]
-pub struct QuadTreeValue{
+pub(crate) struct QuadTreeValue{
     lt: u128,
     rt: u128,
     lb: u128,
     rb: u128,
 }
 impl QuadTreeValue{
-    fn to_array(&self)->[u128;4]{
+    pub(crate) fn to_array(&self)->[u128;4]{
         [self.lt,self.rt,self.lb,self.rb]
     }
-    fn from_array(arr: &[u128;4])->QuadTreeValue{
+    pub(crate) fn from_array(arr: &[u128;4])->QuadTreeValue{
         QuadTreeValue{
             lt: arr[0],
             rt: arr[1],
@@ -34,7 +33,7 @@ impl QuadTreeValue{
     }
 }
 impl QuadTreeValue{
-    fn key(&self) -> u128 {
+    pub(crate) fn key(&self) -> u128 {
         let mut hasher = MetroHash128::new();
         let res: u128;
         unsafe{
@@ -48,20 +47,20 @@ impl QuadTreeValue{
     }
 }
 #[derive(Copy, Clone, Default)]
-struct QuadTreeNode{
-    v: QuadTreeValue,
-    forward_key: u128,
-    set_count: u64,
-    forward_steps: u64,
+pub(crate) struct QuadTreeNode{
+    pub(crate) v: QuadTreeValue,
+    pub(crate) forward_key: u128,
+    pub(crate) set_count: u64,
+    pub(crate) forward_steps: u64,
 }
-const NULL_KEY: u128 = 0xcccccccccccccccccccccccccccccccc;
+pub(crate) const NULL_KEY: u128 = 0xcccccccccccccccccccccccccccccccc;
 pub struct TreeData{
-    map: LargeKeyTable<QuadTreeNode>,
-    black_keys: Vec<u128>,
-    root: u128,
-    depth: u64,
-    offset: Point,
-    age: u64,
+    pub(crate) map: LargeKeyTable<QuadTreeNode>,
+    pub(crate) black_keys: Vec<u128>,
+    pub(crate) root: u128,
+    pub(crate) depth: u64,
+    pub(crate) offset: Point,
+    pub(crate) age: u64,
 }
 
 const BLACK_BASE: u128 = 0;
@@ -83,7 +82,7 @@ impl TreeData{
         tree_data.increase_depth();
         tree_data
     }
-    fn black_key(&mut self, depth:usize) -> u128{
+    pub(crate) fn black_key(&mut self, depth:usize) -> u128{
         //cached method of retreiving the black key for a particular tree level
         match self.black_keys.get(depth){
             Some(x)=>*x,
@@ -110,7 +109,7 @@ impl TreeData{
             d.to_array().iter().map(|x|self.map.get(*x).unwrap().set_count).sum()
         }
     }
-    fn add_array(&mut self, arr: [u128;4])->u128{
+    pub(crate) fn add_array(&mut self, arr: [u128;4])->u128{
         let val = QuadTreeValue::from_array(&arr);
         let key = val.key();
         match self.map.get(key){
@@ -126,7 +125,7 @@ impl TreeData{
         };
         key
     }
-    fn increase_depth(&mut self){
+    pub(crate) fn increase_depth(&mut self){
         let l1m = self.map.get(self.root).unwrap().v.to_array();
         let bkeyd1 = self.black_key((self.depth-1) as usize);
         let smap = [
@@ -145,7 +144,7 @@ impl TreeData{
         let magnitude = (8<<(self.depth-2)) as i64;
         self.offset = self.offset + Point{x:-magnitude,y:-magnitude};
     }
-    fn is_black(&self, key: u128)->bool{
+    pub(crate) fn is_black(&self, key: u128)->bool{
         key == 0 || self.map.get(key).unwrap().set_count == 0
     }
     pub fn step_forward(&mut self, n_steps: u64){
@@ -241,7 +240,7 @@ impl TreeData{
             self.add_array(finalarr)
         }
     }
-    fn add_deps_to_tree(orig_table:&LargeKeyTable<QuadTreeNode>, new_table: &mut LargeKeyTable<QuadTreeNode>, root: u128){
+    pub(crate) fn add_deps_to_tree(orig_table:&LargeKeyTable<QuadTreeNode>, new_table: &mut LargeKeyTable<QuadTreeNode>, root: u128){
         // if not raw value
         if !node_is_raw(root) && new_table.get(root).is_none(){
             let mut node = orig_table.get(root).unwrap();
@@ -267,45 +266,6 @@ impl TreeData{
             age: self.age,
         }
     }
-    pub fn serialize_treerepr(&self)->Vec<u8>{    
-        const SERIAL_SIZE:usize = std::mem::size_of::<(u128,QuadTreeNode)>();
-        const HEADER_SIZE:usize = 8*8;
-        let mut res: Vec<u8> = Vec::with_capacity(self.map.len()*SERIAL_SIZE+HEADER_SIZE);
-        serialize::serialize_transmutable::<u128>(&mut res, self.root);
-        serialize::serialize_transmutable::<Point>(&mut res, self.offset);
-        serialize::serialize_transmutable::<u64>(&mut res, self.depth);
-        serialize::serialize_transmutable::<u64>(&mut res, self.map.len() as u64);
-        serialize::serialize_transmutable::<u64>(&mut res, self.age);
-        self.map.iter(&mut|key,value|{
-            serialize::serialize_transmutable::<(u128,QuadTreeNode)>(&mut res, (*key, *value));
-            true
-        });
-        //no need to serialize black keys, easy enough to recompute, already in tree.
-        res
-    }
-    pub fn deserialize_treerepr(data: &[u8])->TreeData{
-        let mut dataiter = data.iter();
-        let root = serialize::deserialize_transmutable::<u128>(&mut dataiter).unwrap();
-        let offset = serialize::deserialize_transmutable::<Point>(&mut dataiter).unwrap();
-        let depth = serialize::deserialize_transmutable::<u64>(&mut dataiter).unwrap();
-        let length = serialize::deserialize_transmutable::<u64>(&mut dataiter).unwrap();
-        let age = serialize::deserialize_transmutable::<u64>(&mut dataiter).unwrap();
-        let capacity_log2 = 64 - (length+1).leading_zeros() + 1;
-        let mut new_map = LargeKeyTable::new(capacity_log2 as u8);
-        for _ in 0..length{
-            let (key, value) = serialize::deserialize_transmutable::<(u128,QuadTreeNode)>(&mut dataiter).unwrap();
-            new_map.add(key, value);
-        }
-        TreeData{
-            map: new_map,
-            black_keys: vec![BLACK_BASE],
-            root: root,
-            depth: depth,
-            offset: offset,
-            age: age,
-        }
-    }   
-
     fn gather_points_recurive(&mut self, prev_map: &HashMap<Point, u128>, depth: usize) -> HashMap<Point, u128>{
         let mut map: HashMap<Point, u128> = HashMap::new();
         for oldp in prev_map.keys(){
@@ -337,20 +297,48 @@ impl TreeData{
     }
 
     pub fn gather_all_points(points: &Vec<Point>)->TreeData{
-        let mut cur_map = gather_raw_points(&points);
+        if points.is_empty(){
+            return TreeData::new();
+        }
+        // leaves are gathered from non-negative coordinates, so build the tree
+        // from the top left corner of the pattern and shift the offset back afterwards
+        let corner = Point{
+            x: points.iter().map(|p|p.x).min().unwrap(),
+            y: points.iter().map(|p|p.y).min().unwrap(),
+        };
+        let shifted_points: Vec<Point> = points.iter().map(|p|*p + corner.neg()).collect();
+        TreeData::gather_leaves(gather_raw_points(&shifted_points), corner)
+    }
+    // tree from 8x8 leaves keyed by non-negative leaf coordinates, the leaf at (0, 0) having its top left at `corner`
+    pub(crate) fn gather_leaves(leaves: HashMap<Point, u128>, corner: Point)->TreeData{
+        if leaves.is_empty(){
+            return TreeData::new();
+        }
+        let mut cur_map = leaves;
         let mut tree = TreeData::new();
         let mut depth:u64 = 0;
         while cur_map.len() > 1 || depth < 3{
             depth += 1;
             cur_map = tree.gather_points_recurive(&cur_map, depth as usize);
         }
-        let magnitude = (8<<(depth-1)) as i64;
+        let magnitude = (8<<depth) as i64;
         let rootp = *cur_map.keys().next().unwrap();
         tree.root = *cur_map.values().next().unwrap();
         tree.depth = depth;
-        tree.offset = rootp.times(magnitude);
+        tree.offset = rootp.times(magnitude) + corner;
         tree
     }
+    pub fn from_pattern(pattern: &Pattern)->TreeData{
+        let mut tree = TreeData::gather_all_points(&pattern.absolute_points());
+        tree.age = pattern.metadata.generation;
+        tree
+    }
+    pub fn to_pattern(&self, metadata: &PatternMetadata)->Pattern{
+        Pattern::from_absolute_points(&self.dump_all_points(), &PatternMetadata{
+            generation: self.age,
+            ..metadata.clone()
+        })
+    }
     pub fn num_live_cells(&self)->u64{
         self.map.get(self.root).unwrap().set_count
     }
@@ -415,14 +403,12 @@ impl TreeData{
         res
     }
     
-    pub fn make_grayscale_map(&self, offset:Point, xsize: usize, ysize: usize, zoom: u8, brightness: f64) -> Vec<u8> {
-        assert!(zoom >= 0);
-        let mut res: Vec<u8> = Vec::new();
-        res.resize(xsize*ysize, 0);
-        const B2: u8 = 16;
-        let brightness_int = (brightness * (1<<B2) as f64) as u64;
+    // adds the live cells of each 2^zoom square block to its pixel of `counts`
+    pub(crate) fn add_to_count_map(&self, offset:Point, xsize: usize, ysize: usize, zoom: u8, counts: &mut [u64]) {
+        assert!(counts.len() == xsize*ysize);
         self.iter_grayscale_points(self.root, self.depth as i64, offset.neg() + self.offset, &mut|depth,p,count|{
-            let relmag:i64 = 1<<(depth+3 - zoom as i64);
+            // a root smaller than a pixel still covers one
+            let relmag:i64 = 1<<std::cmp::max(depth+3 - zoom as i64, 0);
             let t = p.div(1<<zoom);
             if count == 0{
                 false
@@ -431,15 +417,21 @@ impl TreeData{
                 false
             }
             else if zoom as i64 >= depth+3{
-                let area_log2 = zoom*2;
-                res[(t.y*(xsize as i64)+t.x) as usize] = std::cmp::min(255, (255*brightness_int*count) >> (B2 + area_log2)) as u8;
+                counts[(t.y*(xsize as i64)+t.x) as usize] += count;
                 false
             }
             else{
                 true
             }
         });
-        res
+    }
+    pub fn make_grayscale_map(&self, offset:Point, xsize: usize, ysize: usize, zoom: u8, brightness: f64) -> Vec<u8> {
+        let mut counts = vec![0; xsize*ysize];
+        self.add_to_count_map(offset, xsize, ysize, zoom, &mut counts);
+        const B2: u8 = 16;
+        let brightness_int = (brightness * (1<<B2) as f64) as u64;
+        let area_log2 = zoom*2;
+        counts.iter().map(|count|std::cmp::min(255, (255*brightness_int*count) >> (B2 + area_log2)) as u8).collect()
     }
 }
 
@@ -31,6 +31,10 @@ fn calc_result_bitsize(sums:u64, orig_vals:u64)->u64{
     let res = ((eq4&orig_vals) | eq3) & mask;
     res
 }
+// next state of a single cell with `neighbours` live neighbours, by the rule above
+pub(crate) fn next_cell_state(neighbours: u64, alive: bool)->bool{
+    calc_result_bitsize(neighbours + alive as u64, alive as u64) & 1 != 0
+}
 fn sum_row(row: u64)->u64{
     row + (row<<4) + (row>>4)
 }
@@ -372,6 +376,14 @@ mod tests {
         assert_eq!(actual, expected);
     }
     #[test]
+    fn test_next_cell_state(){
+        // B3/S23
+        for n in 0..=8{
+            assert_eq!(next_cell_state(n, false), n == 3);
+            assert_eq!(next_cell_state(n, true), n == 2 || n == 3);
+        }
+    }
+    #[test]
     fn test_bit4_op(){
         assert_eq!(to_4bit(0xa7),0x10100111);
     }
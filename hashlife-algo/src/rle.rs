@@ -2,8 +2,54 @@
 use std::cmp;
 
 pub use crate::point::Point;
+pub use crate::parse_error::{ParseError, ParseErrorKind};
 
-pub fn iter_coords<F>(boardrow: &str, func: &mut F)
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct PatternMetadata{
+    // `#N` and `#O` lines
+    pub name: Option<String>,
+    pub author: Option<String>,
+    // `#C` lines, in file order
+    pub comments: Vec<String>,
+    // any other `#` lines, kept verbatim
+    pub extra_lines: Vec<String>,
+    // the rule declared in the header, if any
+    pub rule: Option<String>,
+    // position of the top left of the body, from `#CXRLE Pos=`, `#P` or `#R`
+    pub origin: Option<Point>,
+    // generation from `#CXRLE Gen=`
+    pub generation: u64,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Pattern{
+    // cells relative to the top left of the body
+    pub points: Vec<Point>,
+    pub metadata: PatternMetadata,
+}
+
+impl Pattern{
+    // builds a pattern from cells in universe coordinates, recording their top left as the origin
+    pub fn from_absolute_points(points: &[Point], metadata: &PatternMetadata) -> Pattern{
+        let corner = Point{
+            x: points.iter().map(|p|p.x).min().unwrap_or(0),
+            y: points.iter().map(|p|p.y).min().unwrap_or(0),
+        };
+        Pattern{
+            points: points.iter().map(|p|*p + corner.neg()).collect(),
+            metadata: PatternMetadata{
+                origin: Some(corner),
+                ..metadata.clone()
+            },
+        }
+    }
+    pub fn absolute_points(&self) -> Vec<Point>{
+        let origin = self.metadata.origin.unwrap_or_default();
+        self.points.iter().map(|p|*p + origin).collect()
+    }
+}
+
+pub fn iter_coords<F>(body: &str, first_line: usize, declared: Option<(i64,i64)>, func: &mut F) -> Result<(), ParseError>
 where
     F: FnMut(Point)
 {
@@ -11,37 +57,144 @@ where
     let mut ypos: i64 = 0;
     let mut prefixnum: i64 = 0;
     let mut prefixset = false;
-    for c in boardrow.chars(){
-        if c.is_numeric(){
-            prefixnum = prefixnum * 10 + c.to_digit(10).unwrap() as i64;
+    let mut line = first_line;
+    let mut column = 0;
+    // size of the pattern seen so far, and where it first outgrew the header
+    let mut extent = (0, 0);
+    let mut first_overflow: Option<(usize, usize)> = None;
+    for c in body.chars(){
+        column += 1;
+        if let Some(digit) = c.to_digit(10){
+            prefixnum = prefixnum.checked_mul(10)
+                .and_then(|n|n.checked_add(digit as i64))
+                .ok_or(ParseError::new(line, column, ParseErrorKind::RunCountOverflow))?;
             prefixset = true;
         }
         else {
             let repeat = if prefixset {prefixnum} else {1};
             if c == 'b'{
                 // do nothing, blank
-                xpos += repeat;
+                xpos = xpos.checked_add(repeat)
+                    .ok_or(ParseError::new(line, column, ParseErrorKind::RunCountOverflow))?;
             } else if c == 'o'{
-                for i in xpos..(xpos+repeat){
-                    func(Point{x:i,y:ypos});
+                let endx = xpos.checked_add(repeat)
+                    .ok_or(ParseError::new(line, column, ParseErrorKind::RunCountOverflow))?;
+                if repeat > 0{
+                    let endy = ypos.checked_add(1)
+                        .ok_or(ParseError::new(line, column, ParseErrorKind::RunCountOverflow))?;
+                    extent = (cmp::max(extent.0, endx), cmp::max(extent.1, endy));
+                    if let Some((dx, dy)) = declared{
+                        if first_overflow.is_none() && (extent.0 > dx || extent.1 > dy){
+                            first_overflow = Some((line, column));
+                        }
+                    }
                 }
-                xpos += repeat;
+                // past the declared size the body is only scanned for the error, so a
+                // huge run can not make the caller store it
+                if first_overflow.is_none(){
+                    for i in xpos..endx{
+                        func(Point{x:i,y:ypos});
+                    }
+                }
+                xpos = endx;
             } else if c == '$' {
-                ypos += repeat;
+                ypos = ypos.checked_add(repeat)
+                    .ok_or(ParseError::new(line, column, ParseErrorKind::RunCountOverflow))?;
                 xpos = 0;
             } else if c == '!' {
-                    break;
-            } else if c == '\n' || c == '\r'{
-                // do nothing on line break
+                return match (first_overflow, declared){
+                    (Some((l, col)), Some(declared)) => Err(ParseError::new(l, col, ParseErrorKind::SizeMismatch{
+                        declared,
+                        actual: extent,
+                    })),
+                    _ => Ok(()),
+                };
+            } else if c == '\n'{
+                line += 1;
+                column = 0;
+                continue;
+            } else if c.is_whitespace(){
+                // do nothing on whitespace and '\r'
                 continue;
             }
             else{
-                panic!("RLE file incorrectly formatted, only 'b' and 'o' allowed.")
+                return Err(ParseError::new(line, column, ParseErrorKind::UnknownState(c)));
             }
             prefixset = false;
             prefixnum = 0;
         }
     }
+    Err(ParseError::new(line, column + 1, ParseErrorKind::MissingTerminator))
+}
+fn parse_header(header: &str, line: usize) -> Result<(i64,i64,Option<String>), ParseError>{
+    let invalid = |msg: &str| ParseError::new(line, 1, ParseErrorKind::InvalidHeader(msg.to_string()));
+    let mut x: Option<i64> = None;
+    let mut y: Option<i64> = None;
+    let mut rule: Option<String> = None;
+    let mut rest = header;
+    while !rest.is_empty(){
+        let (field, tail) = rest.split_once(',').unwrap_or((rest, ""));
+        let mut parts = field.splitn(2, '=');
+        let name = parts.next().unwrap_or("").trim();
+        let value = parts.next().map(|v|v.trim());
+        match (name, value){
+            ("x", Some(v)) => x = Some(v.parse().map_err(|_|invalid("x is not a number"))?),
+            ("y", Some(v)) => y = Some(v.parse().map_err(|_|invalid("y is not a number"))?),
+            // the rule is the last field and may itself hold commas, as in `B3/S23:T100,100`
+            ("rule", Some(_)) => {
+                rule = rest.split_once('=').map(|(_, v)|v.trim().to_string());
+                break;
+            },
+            _ => return Err(invalid("expected `x = <width>, y = <height>`")),
+        }
+        rest = tail;
+    }
+    match (x, y){
+        (Some(x), Some(y)) => Ok((x, y, rule)),
+        _ => Err(invalid("expected `x = <width>, y = <height>`")),
+    }
+}
+fn tag_text(line: &str) -> String{
+    // the text after a two letter tag, with the separating space removed
+    let rest = line.get(2..).unwrap_or("");
+    rest.strip_prefix(' ').unwrap_or(rest).to_string()
+}
+fn parse_metadata_line(line: &str, line_no: usize, metadata: &mut PatternMetadata) -> Result<(), ParseError>{
+    let invalid = |msg: &str| ParseError::new(line_no, 1, ParseErrorKind::InvalidMetadata(msg.to_string()));
+    if let Some(fields) = line.strip_prefix("#CXRLE"){
+        for field in fields.split_whitespace(){
+            if let Some(pos) = field.strip_prefix("Pos="){
+                let coords: Vec<Option<i64>> = pos.split(',').map(|v|v.parse().ok()).collect();
+                match coords[..]{
+                    [Some(x), Some(y)] => metadata.origin = Some(Point{x, y}),
+                    _ => return Err(invalid("expected `Pos=<x>,<y>`")),
+                }
+            }
+            else if let Some(gen) = field.strip_prefix("Gen="){
+                metadata.generation = gen.parse().map_err(|_|invalid("expected `Gen=<generation>`"))?;
+            }
+        }
+    }
+    else if line.starts_with("#C") || line.starts_with("#c"){
+        metadata.comments.push(tag_text(line));
+    }
+    else if line.starts_with("#N"){
+        metadata.name = Some(tag_text(line));
+    }
+    else if line.starts_with("#O"){
+        metadata.author = Some(tag_text(line));
+    }
+    else if line.starts_with("#P") || line.starts_with("#R"){
+        let coords: Vec<Option<i64>> = line.get(2..).unwrap_or("").split_whitespace().map(|v|v.parse().ok()).collect();
+        match coords[..]{
+            [Some(x), Some(y)] => metadata.origin = Some(Point{x, y}),
+            _ => return Err(invalid("expected `#P <x> <y>`")),
+        }
+    }
+    else{
+        metadata.extra_lines.push(line.to_string());
+    }
+    Ok(())
 }
 pub fn generate_rle_contents(points:& Vec<Point>) -> String{
     let mut s = String::new();
@@ -102,15 +255,53 @@ pub fn compress_os(orig_str: &str)->String{
 }
 
 pub fn write_rle(points:& Vec<Point>) -> String{
+    write_rle_parts(points, &PatternMetadata::default())
+}
+pub fn write_rle_pattern(pattern: &Pattern) -> String{
+    write_rle_parts(&pattern.points, &pattern.metadata)
+}
+fn write_rle_parts(points: &Vec<Point>, metadata: &PatternMetadata) -> String{
     let mut s: String = String::new();
-    let minx = points.iter().map(|p|p.x).min().unwrap();
-    let miny = points.iter().map(|p|p.y).min().unwrap();
-    let maxx = points.iter().map(|p|p.x).max().unwrap();
-    let maxy = points.iter().map(|p|p.y).max().unwrap();
-
-    s.push_str(format!("x = {}, y = {}, rule = B3/S23\n", maxx-minx+1, maxy-miny+1).as_str());
+    let (minx, miny, maxx, maxy) = if points.is_empty(){
+        (0, 0, -1, -1)
+    } else {(
+        points.iter().map(|p|p.x).min().unwrap(),
+        points.iter().map(|p|p.y).min().unwrap(),
+        points.iter().map(|p|p.x).max().unwrap(),
+        points.iter().map(|p|p.y).max().unwrap(),
+    )};
+    if let Some(name) = &metadata.name{
+        s.push_str(format!("#N {}\n", name).as_str());
+    }
+    if let Some(author) = &metadata.author{
+        s.push_str(format!("#O {}\n", author).as_str());
+    }
+    for comment in metadata.comments.iter(){
+        if comment.is_empty(){
+            s.push_str("#C\n");
+        } else {
+            s.push_str(format!("#C {}\n", comment).as_str());
+        }
+    }
+    for line in metadata.extra_lines.iter(){
+        s.push_str(line.as_str());
+        s.push('\n');
+    }
+    if metadata.origin.is_some() || metadata.generation != 0{
+        s.push_str("#CXRLE");
+        if let Some(origin) = metadata.origin{
+            // the body is written from the top left live cell, so shift the position to match
+            s.push_str(format!(" Pos={},{}", origin.x + minx, origin.y + miny).as_str());
+        }
+        if metadata.generation != 0{
+            s.push_str(format!(" Gen={}", metadata.generation).as_str());
+        }
+        s.push('\n');
+    }
+    let rule = metadata.rule.as_deref().unwrap_or("B3/S23");
+    s.push_str(format!("x = {}, y = {}, rule = {}\n", maxx-minx+1, maxy-miny+1, rule).as_str());
     s.push_str(split_string_to_lines(compress_os(generate_rle_contents(points).as_str()).as_str(), 70).as_str());
-    return s;
+    s
 }
 
 fn cdiv(x:i64, y: i64) -> i64{
@@ -130,25 +321,30 @@ pub fn split_string_to_lines(ins: &str, spacing:i64) -> String{
     return outs;
 }
 
-pub fn parse_fle_file(file_contents: &str) -> Vec<Point> {
-    let mut line_iter = file_contents.lines(); 
-    // skips comments and metadata
-    while let Some(line) = line_iter.next() {
-        if !line.starts_with("#"){
-            break;
-        }
-    }
-    // remove endlines and put together remainder of file
-    let mut remaining_str = String::new();
-    while let Some(line) = line_iter.next() {
-        remaining_str.push_str(line);
+pub fn parse_fle_file(file_contents: &str) -> Result<Vec<Point>, ParseError> {
+    parse_rle(file_contents).map(|pattern|pattern.points)
+}
+
+pub fn parse_rle(file_contents: &str) -> Result<Pattern, ParseError> {
+    let lines: Vec<&str> = file_contents.split('\n').collect();
+    let mut metadata = PatternMetadata::default();
+    // reads comments and metadata up to the header
+    let header_idx = lines.iter().position(|line|!line.starts_with('#')).ok_or(
+        ParseError::new(lines.len(), 1, ParseErrorKind::InvalidHeader("missing header line".to_string()))
+    )?;
+    for (i, line) in lines[..header_idx].iter().enumerate(){
+        parse_metadata_line(line.trim_end_matches('\r'), i+1, &mut metadata)?;
     }
+    let (width, height, rule) = parse_header(lines[header_idx].trim(), header_idx+1)?;
+    metadata.rule = rule;
+    // keep the line breaks in the body so that errors can be located
+    let body = lines[header_idx+1..].join("\n");
     //iterate through remainder
     let mut points: Vec<Point> = Vec::new();
-    iter_coords(remaining_str.as_str(), &mut|p|{
+    iter_coords(body.as_str(), header_idx+2, Some((width, height)), &mut|p|{
         points.push(p);
-    });
-    return points;
+    })?;
+    Ok(Pattern{points, metadata})
 }
 
 
@@ -169,14 +365,14 @@ mod tests {
             "5bob2o$4bo6bo$3b2o3bo2bo$2obo5b2o$2obo5b2o$3b2o3bo2bo$4bo6bo$5bob2o!\n"
         );
 
-        let points = parse_fle_file(contents);
+        let points = parse_fle_file(contents).unwrap();
         let rle_tot_str = write_rle(&points);
         assert_eq!(expected, rle_tot_str);
     }
     #[test]
     fn test_load_dump_points_large() {
         let contents = concat!(
-            "x = 12, y = 8, rule = B3/S23\n",
+            "x = 49, y = 22, rule = B3/S23\n",
             "12bo8bo$bo2bo2b2o2bo25bo2b2o2bo2bo$6bo5bo7b3o3b3o7bo5bo$6bo5bo8bo5bo8bo5bo$6bo5bo8b7o8bo5bo$bo2bo2b2o2bo2b2o4bo7bo4b2o2bo2b2o2bo2bo$o8bo3b2o4b11o4b2o3bo8bo$o3bo9b2o17b2o9bo3bo$4o11b19o11b4o$16bobo11bobo$19b11o$19bo9bo$20b9o$24bo$20b3o3b3o$22bo3bo$$21b3ob3o$21b3ob3o$20bob2ob2obo$20b3o3b3o$21bo5bo!\n"
         );
         let expected = concat!(
@@ -184,8 +380,80 @@ mod tests {
             "12bo8bo$bo2bo2b2o2bo25bo2b2o2bo2bo$6bo5bo7b3o3b3o7bo5bo$6bo5bo8bo5bo8b\no5bo$6bo5bo8b7o8bo5bo$bo2bo2b2o2bo2b2o4bo7bo4b2o2bo2b2o2bo2bo$o8bo3b2o\n4b11o4b2o3bo8bo$o3bo9b2o17b2o9bo3bo$4o11b19o11b4o$16bobo11bobo$19b11o$\n19bo9bo$20b9o$24bo$20b3o3b3o$22bo3bo$$21b3ob3o$21b3ob3o$20bob2ob2obo$20b\n3o3b3o$21bo5bo!\n"
         );
 
-        let points = parse_fle_file(contents);
+        let points = parse_fle_file(contents).unwrap();
         let rle_tot_str = write_rle(&points);
         assert_eq!(expected, rle_tot_str);
     }
-}
\ No newline at end of file
+    #[test]
+    fn test_write_empty() {
+        assert_eq!(write_rle(&Vec::new()), "x = 0, y = 0, rule = B3/S23\n!\n");
+    }
+    #[test]
+    fn test_parse_errors() {
+        let err = parse_fle_file("#C glider\nx = 3, y = 3\nbo$2bo$3q!\n").unwrap_err();
+        assert_eq!(err, ParseError::new(3, 9, ParseErrorKind::UnknownState('q')));
+
+        let err = parse_fle_file("x = 3, y = 3\nbo$2bo$\n3o\n").unwrap_err();
+        assert_eq!(err, ParseError::new(4, 1, ParseErrorKind::MissingTerminator));
+
+        let err = parse_fle_file("x = 2, y = 3\nbo$2bo$3o!\n").unwrap_err();
+        assert_eq!(err, ParseError::new(2, 6, ParseErrorKind::SizeMismatch{declared: (2, 3), actual: (3, 3)}));
+
+        let err = parse_fle_file("x = 3, y = 3\n99999999999999999999o!\n").unwrap_err();
+        assert_eq!(err.kind, ParseErrorKind::RunCountOverflow);
+
+        let err = parse_fle_file("x = 3, y = 3\n9223372036854775807$o!\n").unwrap_err();
+        assert_eq!(err, ParseError::new(2, 21, ParseErrorKind::RunCountOverflow));
+
+        // a run far past the declared size is reported without being expanded
+        let mut cells = 0;
+        let err = iter_coords("999999999999o!", 2, Some((3, 3)), &mut |_|cells += 1).unwrap_err();
+        assert_eq!(err, ParseError::new(2, 13, ParseErrorKind::SizeMismatch{declared: (3, 3), actual: (999999999999, 1)}));
+        assert_eq!(cells, 0);
+
+        let err = parse_fle_file("bo$2bo$3o!\n").unwrap_err();
+        assert_eq!((err.line, err.column), (1, 1));
+        assert!(matches!(err.kind, ParseErrorKind::InvalidHeader(_)));
+    }
+    #[test]
+    fn test_metadata_round_trip() {
+        let contents = concat!(
+            "#N Glider\n",
+            "#O Richard K. Guy\n",
+            "#C The smallest, most common, and first discovered spaceship.\n",
+            "#C\n",
+            "# untagged line\n",
+            "#CXRLE Pos=-3,5 Gen=12\n",
+            "x = 3, y = 3, rule = B3/S23\n",
+            "bo$2bo$3o!\n"
+        );
+        let pattern = parse_rle(contents).unwrap();
+        assert_eq!(pattern.metadata.name.as_deref(), Some("Glider"));
+        assert_eq!(pattern.metadata.author.as_deref(), Some("Richard K. Guy"));
+        assert_eq!(pattern.metadata.comments.len(), 2);
+        assert_eq!(pattern.metadata.extra_lines, vec!["# untagged line".to_string()]);
+        assert_eq!(pattern.metadata.rule.as_deref(), Some("B3/S23"));
+        assert_eq!(pattern.metadata.origin, Some(Point{x:-3, y:5}));
+        assert_eq!(pattern.metadata.generation, 12);
+        let torus = parse_rle("x = 3, y = 3, rule = B3/S23:T100,100\nbo$2bo$3o!\n").unwrap();
+        assert_eq!(torus.metadata.rule.as_deref(), Some("B3/S23:T100,100"));
+        assert_eq!(pattern.absolute_points()[0], Point{x:-2, y:5});
+        assert_eq!(write_rle_pattern(&pattern), contents);
+    }
+    #[test]
+    fn test_example_files_round_trip() {
+        let examples = [
+            include_str!("../../frontend/examples/52513m.rle"),
+            include_str!("../../frontend/examples/ark1.rle"),
+            include_str!("../../frontend/examples/lidka-predecessor.rle"),
+        ];
+        for contents in examples.iter(){
+            let pattern = parse_rle(contents).unwrap();
+            assert!(!pattern.metadata.comments.is_empty() || !pattern.metadata.extra_lines.is_empty());
+            let written = write_rle_pattern(&pattern);
+            assert_eq!(parse_rle(&written).unwrap(), pattern);
+        }
+        let contents = include_str!("../../frontend/examples/52513m.rle");
+        assert_eq!(write_rle_pattern(&parse_rle(contents).unwrap()).trim_end(), contents.trim_end());
+    }
+}
@@ -0,0 +1,498 @@
+/*
+A small CDCL SAT solver, so that searches posed as boolean constraints
+need no outside dependency.
+
+Literals are numbered 2*var for the variable and 2*var+1 for its
+negation. It uses two watched literals per clause, learns first UIP
+clauses with their redundant literals removed, picks variables by
+VSIDS activity from a heap, remembers the last value of each variable,
+and restarts on the Luby sequence. Learnt clauses are kept for good, so
+long searches should be bounded with a conflict limit.
+*/
+
+pub(crate) type Lit = u32;
+
+pub(crate) fn pos(var: u32) -> Lit{
+    var * 2
+}
+pub(crate) fn neg(var: u32) -> Lit{
+    var * 2 + 1
+}
+fn var_of(lit: Lit) -> usize{
+    (lit / 2) as usize
+}
+
+const UNASSIGNED: i8 = 0;
+// conflicts in the first restart interval, scaled by the Luby sequence
+const RESTART_BASE: u64 = 100;
+const ACTIVITY_DECAY: f64 = 0.95;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) enum SatResult{
+    // value of every variable
+    Sat(Vec<bool>),
+    Unsat,
+    // gave up at the conflict limit
+    Unknown,
+}
+
+// i-th element of the Luby sequence 1, 1, 2, 1, 1, 2, 4, ...
+fn luby(mut i: u64) -> u64{
+    let mut size = 1;
+    let mut seq = 0;
+    while size < i + 1{
+        seq += 1;
+        size = 2*size + 1;
+    }
+    while size - 1 != i{
+        size = (size - 1) / 2;
+        seq -= 1;
+        i %= size;
+    }
+    1 << seq
+}
+
+#[derive(Default)]
+pub(crate) struct Solver{
+    clauses: Vec<Vec<Lit>>,
+    // clauses watching each literal, visited when it becomes false
+    watches: Vec<Vec<usize>>,
+    // 1 for true, -1 for false, by variable
+    assigns: Vec<i8>,
+    level: Vec<usize>,
+    reason: Vec<Option<usize>>,
+    saved_phase: Vec<bool>,
+    trail: Vec<Lit>,
+    trail_lim: Vec<usize>,
+    queue_head: usize,
+    activity: Vec<f64>,
+    var_inc: f64,
+    // binary max heap of variables by activity, with each variable's place in it
+    heap: Vec<u32>,
+    heap_index: Vec<Option<usize>>,
+    // set once the clauses are known to be unsatisfiable
+    broken: bool,
+}
+
+impl Solver{
+    pub(crate) fn new() -> Solver{
+        Solver{var_inc: 1.0, ..Solver::default()}
+    }
+    pub(crate) fn new_var(&mut self) -> u32{
+        let var = self.assigns.len() as u32;
+        self.assigns.push(UNASSIGNED);
+        self.level.push(0);
+        self.reason.push(None);
+        self.saved_phase.push(false);
+        self.activity.push(0.0);
+        self.heap_index.push(None);
+        self.watches.push(Vec::new());
+        self.watches.push(Vec::new());
+        self.heap_insert(var);
+        var
+    }
+    fn value(&self, lit: Lit) -> i8{
+        let v = self.assigns[var_of(lit)];
+        if lit & 1 == 1 {-v} else {v}
+    }
+    fn decision_level(&self) -> usize{
+        self.trail_lim.len()
+    }
+    fn assign(&mut self, lit: Lit, reason: Option<usize>){
+        let var = var_of(lit);
+        self.assigns[var] = if lit & 1 == 1 {-1} else {1};
+        self.level[var] = self.decision_level();
+        self.reason[var] = reason;
+        self.trail.push(lit);
+    }
+    // adds a clause before solving starts
+    pub(crate) fn add_clause(&mut self, lits: &[Lit]){
+        if self.broken{
+            return;
+        }
+        let mut clause: Vec<Lit> = Vec::with_capacity(lits.len());
+        for lit in lits.iter(){
+            if clause.contains(&(lit ^ 1)) || self.value(*lit) == 1{
+                return;
+            }
+            if !clause.contains(lit) && self.value(*lit) != -1{
+                clause.push(*lit);
+            }
+        }
+        match clause.len(){
+            0 => {
+                self.broken = true;
+            },
+            1 => {
+                self.assign(clause[0], None);
+                if self.propagate().is_some(){
+                    self.broken = true;
+                }
+            },
+            _ => {
+                self.attach(clause);
+            },
+        }
+    }
+    // at most `k` of the variables are true, counted with a chain of partial sums
+    pub(crate) fn add_at_most(&mut self, vars: &[u32], k: usize){
+        if k == 0{
+            for var in vars.iter(){
+                self.add_clause(&[neg(*var)]);
+            }
+            return;
+        }
+        // sums[j]: at least j+1 of the variables so far are true
+        let mut sums: Vec<u32> = Vec::new();
+        for (i, var) in vars.iter().enumerate(){
+            if i > 0{
+                self.add_clause(&[neg(*var), neg(sums[k - 1])]);
+            }
+            if i + 1 == vars.len(){
+                break;
+            }
+            let next: Vec<u32> = (0..k).map(|_|self.new_var()).collect();
+            self.add_clause(&[neg(*var), pos(next[0])]);
+            for j in 0..k{
+                if i > 0{
+                    self.add_clause(&[neg(sums[j]), pos(next[j])]);
+                    if j > 0{
+                        self.add_clause(&[neg(*var), neg(sums[j - 1]), pos(next[j])]);
+                    }
+                }
+                else if j > 0{
+                    self.add_clause(&[neg(next[j])]);
+                }
+            }
+            sums = next;
+        }
+    }
+    fn attach(&mut self, clause: Vec<Lit>) -> usize{
+        let index = self.clauses.len();
+        self.watches[clause[0] as usize].push(index);
+        self.watches[clause[1] as usize].push(index);
+        self.clauses.push(clause);
+        index
+    }
+    // returns the conflicting clause, if any
+    fn propagate(&mut self) -> Option<usize>{
+        while self.queue_head < self.trail.len(){
+            let false_lit = self.trail[self.queue_head] ^ 1;
+            self.queue_head += 1;
+            let mut watching = std::mem::take(&mut self.watches[false_lit as usize]);
+            let mut kept = 0;
+            let mut i = 0;
+            while i < watching.len(){
+                let index = watching[i];
+                i += 1;
+                if self.clauses[index][0] == false_lit{
+                    self.clauses[index].swap(0, 1);
+                }
+                let first = self.clauses[index][0];
+                if self.value(first) == 1{
+                    watching[kept] = index;
+                    kept += 1;
+                    continue;
+                }
+                let replacement = (2..self.clauses[index].len()).find(|k|self.value(self.clauses[index][*k]) != -1);
+                if let Some(k) = replacement{
+                    self.clauses[index].swap(1, k);
+                    let lit = self.clauses[index][1];
+                    self.watches[lit as usize].push(index);
+                    continue;
+                }
+                watching[kept] = index;
+                kept += 1;
+                if self.value(first) == -1{
+                    while i < watching.len(){
+                        watching[kept] = watching[i];
+                        kept += 1;
+                        i += 1;
+                    }
+                    watching.truncate(kept);
+                    self.watches[false_lit as usize] = watching;
+                    return Some(index);
+                }
+                self.assign(first, Some(index));
+            }
+            watching.truncate(kept);
+            self.watches[false_lit as usize] = watching;
+        }
+        None
+    }
+    // first UIP clause learnt from a conflict, with the level to go back to
+    fn analyze(&mut self, conflict: usize) -> (Vec<Lit>, usize){
+        let mut seen = vec![false; self.assigns.len()];
+        let mut learnt: Vec<Lit> = vec![0];
+        let mut pending = 0;
+        let mut index = self.trail.len();
+        let mut clause = conflict;
+        let mut implied: Option<Lit> = None;
+        loop{
+            let start = if implied.is_some() {1} else {0};
+            for k in start..self.clauses[clause].len(){
+                let lit = self.clauses[clause][k];
+                let var = var_of(lit);
+                if !seen[var] && self.level[var] > 0{
+                    seen[var] = true;
+                    self.bump(var);
+                    if self.level[var] >= self.decision_level(){
+                        pending += 1;
+                    }
+                    else{
+                        learnt.push(lit);
+                    }
+                }
+            }
+            loop{
+                index -= 1;
+                if seen[var_of(self.trail[index])]{
+                    break;
+                }
+            }
+            let lit = self.trail[index];
+            implied = Some(lit);
+            seen[var_of(lit)] = false;
+            pending -= 1;
+            if pending == 0{
+                break;
+            }
+            clause = self.reason[var_of(lit)].unwrap();
+        }
+        learnt[0] = implied.unwrap() ^ 1;
+        // drop literals implied by the others
+        let redundant: Vec<bool> = learnt.iter().enumerate().map(|(k, lit)|{
+            k > 0 && self.reason[var_of(*lit)].is_some_and(|r|{
+                self.clauses[r][1..].iter().all(|l|seen[var_of(*l)] || self.level[var_of(*l)] == 0)
+            })
+        }).collect();
+        let mut learnt: Vec<Lit> = learnt.into_iter().zip(redundant).filter(|(_, r)|!r).map(|(l, _)|l).collect();
+        let mut back_level = 0;
+        if learnt.len() > 1{
+            let highest = (1..learnt.len()).max_by_key(|k|self.level[var_of(learnt[*k])]).unwrap();
+            learnt.swap(1, highest);
+            back_level = self.level[var_of(learnt[1])];
+        }
+        (learnt, back_level)
+    }
+    fn cancel_until(&mut self, level: usize){
+        if self.decision_level() <= level{
+            return;
+        }
+        let keep = self.trail_lim[level];
+        while self.trail.len() > keep{
+            let lit = self.trail.pop().unwrap();
+            let var = var_of(lit);
+            self.assigns[var] = UNASSIGNED;
+            self.reason[var] = None;
+            self.saved_phase[var] = lit & 1 == 0;
+            if self.heap_index[var].is_none(){
+                self.heap_insert(var as u32);
+            }
+        }
+        self.trail_lim.truncate(level);
+        self.queue_head = keep;
+    }
+    fn bump(&mut self, var: usize){
+        self.activity[var] += self.var_inc;
+        if self.activity[var] > 1e100{
+            for a in self.activity.iter_mut(){
+                *a *= 1e-100;
+            }
+            self.var_inc *= 1e-100;
+        }
+        if let Some(i) = self.heap_index[var]{
+            self.heap_up(i);
+        }
+    }
+    fn heap_insert(&mut self, var: u32){
+        self.heap.push(var);
+        let i = self.heap.len() - 1;
+        self.heap_index[var as usize] = Some(i);
+        self.heap_up(i);
+    }
+    fn heap_swap(&mut self, i: usize, j: usize){
+        self.heap.swap(i, j);
+        self.heap_index[self.heap[i] as usize] = Some(i);
+        self.heap_index[self.heap[j] as usize] = Some(j);
+    }
+    fn heap_up(&mut self, mut i: usize){
+        while i > 0{
+            let parent = (i - 1) / 2;
+            if self.activity[self.heap[parent] as usize] >= self.activity[self.heap[i] as usize]{
+                break;
+            }
+            self.heap_swap(i, parent);
+            i = parent;
+        }
+    }
+    fn heap_pop(&mut self) -> Option<u32>{
+        let top = *self.heap.first()?;
+        let last = self.heap.len() - 1;
+        self.heap_swap(0, last);
+        self.heap.pop();
+        self.heap_index[top as usize] = None;
+        let mut i = 0;
+        loop{
+            let (l, r) = (2*i + 1, 2*i + 2);
+            let mut largest = i;
+            for child in [l, r]{
+                if child < self.heap.len() && self.activity[self.heap[child] as usize] > self.activity[self.heap[largest] as usize]{
+                    largest = child;
+                }
+            }
+            if largest == i{
+                break;
+            }
+            self.heap_swap(i, largest);
+            i = largest;
+        }
+        Some(top)
+    }
+    pub(crate) fn solve(&mut self, max_conflicts: Option<u64>) -> SatResult{
+        if self.broken{
+            return SatResult::Unsat;
+        }
+        let mut conflicts = 0;
+        let mut restarts = 0;
+        let mut restart_at = RESTART_BASE * luby(0);
+        loop{
+            if let Some(conflict) = self.propagate(){
+                conflicts += 1;
+                if self.decision_level() == 0{
+                    self.broken = true;
+                    return SatResult::Unsat;
+                }
+                let (learnt, back_level) = self.analyze(conflict);
+                self.cancel_until(back_level);
+                if learnt.len() == 1{
+                    self.assign(learnt[0], None);
+                }
+                else{
+                    let first = learnt[0];
+                    let index = self.attach(learnt);
+                    self.assign(first, Some(index));
+                }
+                self.var_inc /= ACTIVITY_DECAY;
+                if max_conflicts.is_some_and(|max|conflicts >= max){
+                    self.cancel_until(0);
+                    return SatResult::Unknown;
+                }
+                if conflicts >= restart_at{
+                    restarts += 1;
+                    restart_at = conflicts + RESTART_BASE * luby(restarts);
+                    self.cancel_until(0);
+                }
+                continue;
+            }
+            let next = loop{
+                match self.heap_pop(){
+                    Some(var) if self.assigns[var as usize] != UNASSIGNED => continue,
+                    other => break other,
+                }
+            };
+            match next{
+                Some(var) => {
+                    self.trail_lim.push(self.trail.len());
+                    let lit = if self.saved_phase[var as usize] {pos(var)} else {neg(var)};
+                    self.assign(lit, None);
+                },
+                None => {
+                    let model = self.assigns.iter().map(|v|*v == 1).collect();
+                    self.cancel_until(0);
+                    return SatResult::Sat(model);
+                },
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    // Note this useful idiom: importing names from outer (for mod tests) scope.
+    use super::*;
+
+    fn satisfies(clauses: &[Vec<Lit>], model: &[bool]) -> bool{
+        clauses.iter().all(|c|c.iter().any(|l|model[var_of(*l)] == (l & 1 == 0)))
+    }
+    #[test]
+    fn test_luby() {
+        let seq: Vec<u64> = (0..15).map(luby).collect();
+        assert_eq!(seq, vec![1, 1, 2, 1, 1, 2, 4, 1, 1, 2, 1, 1, 2, 4, 8]);
+    }
+    #[test]
+    fn test_pigeonhole() {
+        // n+1 pigeons in n holes is unsatisfiable, n pigeons in n holes is not
+        for (pigeons, holes, sat) in [(5, 4, false), (4, 4, true)]{
+            let mut solver = Solver::new();
+            let vars: Vec<Vec<u32>> = (0..pigeons).map(|_|(0..holes).map(|_|solver.new_var()).collect()).collect();
+            let mut clauses = Vec::new();
+            for pigeon in vars.iter(){
+                clauses.push(pigeon.iter().map(|v|pos(*v)).collect::<Vec<Lit>>());
+            }
+            for (a, first) in vars.iter().enumerate(){
+                for second in vars[a+1..].iter(){
+                    for (x, y) in first.iter().zip(second.iter()){
+                        clauses.push(vec![neg(*x), neg(*y)]);
+                    }
+                }
+            }
+            for c in clauses.iter(){
+                solver.add_clause(c);
+            }
+            match solver.solve(None){
+                SatResult::Sat(model) => assert!(sat && satisfies(&clauses, &model)),
+                result => assert!(!sat && result == SatResult::Unsat),
+            }
+        }
+    }
+    #[test]
+    fn test_at_most() {
+        // at most k of 6, with at least `required` of them forced by a clause per subset of misses
+        for k in 0..4{
+            for required in 0..6{
+                let mut solver = Solver::new();
+                let vars: Vec<u32> = (0..6).map(|_|solver.new_var()).collect();
+                solver.add_at_most(&vars, k);
+                for mask in 0..64u32{
+                    if mask.count_ones() as usize == 6 - required + 1{
+                        let clause: Vec<Lit> = (0..6).filter(|i|mask >> i & 1 == 1).map(|i|pos(vars[i])).collect();
+                        solver.add_clause(&clause);
+                    }
+                }
+                match solver.solve(None){
+                    SatResult::Sat(model) => {
+                        let count = vars.iter().filter(|v|model[**v as usize]).count();
+                        assert!(required <= k && count <= k && count >= required);
+                    },
+                    result => assert!(required > k && result == SatResult::Unsat),
+                }
+            }
+        }
+    }
+    #[test]
+    fn test_random_3sat() {
+        // random formulas checked against every assignment
+        let mut seed: u64 = 12345;
+        let mut next = ||{
+            seed = seed.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+            (seed >> 33) as u32
+        };
+        for _ in 0..40{
+            let n = 10;
+            let clauses: Vec<Vec<Lit>> = (0..45).map(|_|(0..3).map(|_|next() % (2*n)).collect()).collect();
+            let mut solver = Solver::new();
+            for _ in 0..n{
+                solver.new_var();
+            }
+            for c in clauses.iter(){
+                solver.add_clause(c);
+            }
+            let brute = (0..1u32 << n).any(|m|satisfies(&clauses, &(0..n).map(|v|m >> v & 1 == 1).collect::<Vec<bool>>()));
+            match solver.solve(None){
+                SatResult::Sat(model) => assert!(satisfies(&clauses, &model)),
+                result => assert!(!brute && result == SatResult::Unsat),
+            }
+        }
+    }
+}
@@ -1,33 +1,251 @@
-use std::mem::{transmute, size_of};
-
-pub fn serialize_transmutable<T:Copy>(vec: &mut Vec<u8>, x:T){
-    unsafe{
-        let N_BYTES:usize = size_of::<T>();
-        let x_ptr: *const T = &x;
-        let byte_ptr: *const u8 = transmute(x_ptr);
-        
-        for i in 0..N_BYTES{
-            vec.push(*byte_ptr.add(i));
-        }
-    }
-}
-pub fn deserialize_transmutable<'a,T:Copy + Default>(dataiter: &mut core::slice::Iter<'a,u8>)->Option<T>{
-    unsafe{
-        let mut res: T = Default::default();
-        let N_BYTES:usize = size_of::<T>();
-        let x_ptr: *mut T = &mut res;
-        let byte_ptr: *mut u8 = transmute(x_ptr);
-        for i in 0..N_BYTES{
-            match dataiter.next(){
-                Some(v)=>{
-                    *byte_ptr.add(i) = *v;
-                },
-                None=>{
-                    return None
-                }
+/*
+Portable binary format for TreeData.
+
+All integers are little endian.
+
+    magic       4 bytes   "HLQT"
+    version     u32       FORMAT_VERSION
+    rule        u8 length followed by that many bytes of ASCII, e.g. "B3/S23"
+    offset      i64 x, i64 y, the top left corner of the root
+    depth       u64       depth of the root node
+    age         u64       generation of the pattern
+    node count  u64
+    nodes       node count entries of:
+                    level   u8, 1 for nodes whose children are 8x8 leaves
+                    children 4 x u64 in (top left, top right, bottom left, bottom right) order,
+                            the leaf bits (bit y*8+x) for level 1 nodes, otherwise
+                            the index of an earlier node of level-1
+    checksum    u64       FNV-1a hash of every preceding byte
+
+Only the nodes reachable from the root are written, children before parents,
+and the root is the last node. Node keys and the step cache are not stored,
+keys are recomputed from the children when the tree is loaded.
+The incremental variant used to keep two copies in sync is in delta.rs.
+*/
+
+use std::collections::HashMap;
+use std::fmt;
+
+use crate::point::Point;
+use crate::quadtree::TreeData;
+use crate::raw_ops::node_is_raw;
+
+const MAGIC: &[u8; 4] = b"HLQT";
+const FORMAT_VERSION: u32 = 1;
+const RULE: &str = "B3/S23";
+// 8 << depth has to fit in an i64
+const MAX_DEPTH: u64 = 59;
+const NODE_SIZE: usize = 1 + 4*8;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum DecodeError{
+    BadMagic,
+    UnsupportedVersion(u32),
+    UnsupportedRule(String),
+    Truncated,
+    TrailingData,
+    ChecksumMismatch,
+    InvalidTree(String),
+}
+
+impl fmt::Display for DecodeError{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result{
+        match self{
+            DecodeError::BadMagic => write!(f, "not a serialized tree"),
+            DecodeError::UnsupportedVersion(v) => write!(f, "unsupported format version {}", v),
+            DecodeError::UnsupportedRule(rule) => write!(f, "unsupported rule '{}', only {} is implemented", rule, RULE),
+            DecodeError::Truncated => write!(f, "data ends early"),
+            DecodeError::TrailingData => write!(f, "unexpected data after the checksum"),
+            DecodeError::ChecksumMismatch => write!(f, "checksum does not match, data is corrupt"),
+            DecodeError::InvalidTree(msg) => write!(f, "invalid tree: {}", msg),
+        }
+    }
+}
+
+impl std::error::Error for DecodeError{}
+
+pub(crate) fn fnv1a(data: &[u8]) -> u64{
+    data.iter().fold(0xcbf29ce484222325, |hash, b|(hash ^ *b as u64).wrapping_mul(0x100000001b3))
+}
+
+pub(crate) struct Reader<'a>{
+    data: &'a [u8],
+    pos: usize,
+}
+impl<'a> Reader<'a>{
+    fn bytes(&mut self, n: usize) -> Result<&'a [u8], DecodeError>{
+        if self.data.len() - self.pos < n{
+            return Err(DecodeError::Truncated);
+        }
+        let res = &self.data[self.pos..self.pos+n];
+        self.pos += n;
+        Ok(res)
+    }
+    pub(crate) fn remaining(&self) -> usize{
+        self.data.len() - self.pos
+    }
+    pub(crate) fn u8(&mut self) -> Result<u8, DecodeError>{
+        Ok(self.bytes(1)?[0])
+    }
+    pub(crate) fn u32(&mut self) -> Result<u32, DecodeError>{
+        Ok(u32::from_le_bytes(self.bytes(4)?.try_into().unwrap()))
+    }
+    pub(crate) fn u64(&mut self) -> Result<u64, DecodeError>{
+        Ok(u64::from_le_bytes(self.bytes(8)?.try_into().unwrap()))
+    }
+    pub(crate) fn i64(&mut self) -> Result<i64, DecodeError>{
+        Ok(i64::from_le_bytes(self.bytes(8)?.try_into().unwrap()))
+    }
+}
+
+pub(crate) fn write_preamble(res: &mut Vec<u8>, magic: &[u8;4]){
+    res.extend_from_slice(magic);
+    res.extend_from_slice(&FORMAT_VERSION.to_le_bytes());
+    res.push(RULE.len() as u8);
+    res.extend_from_slice(RULE.as_bytes());
+}
+pub(crate) fn write_placement(res: &mut Vec<u8>, tree: &TreeData){
+    res.extend_from_slice(&tree.offset.x.to_le_bytes());
+    res.extend_from_slice(&tree.offset.y.to_le_bytes());
+    res.extend_from_slice(&tree.depth.to_le_bytes());
+    res.extend_from_slice(&tree.age.to_le_bytes());
+}
+pub(crate) fn seal(res: &mut Vec<u8>){
+    let checksum = fnv1a(res);
+    res.extend_from_slice(&checksum.to_le_bytes());
+}
+// checks the magic, version, checksum and rule, returning a reader over the rest of the body
+pub(crate) fn open_blob<'a>(data: &'a [u8], magic: &[u8;4]) -> Result<Reader<'a>, DecodeError>{
+    if data.len() < magic.len() || &data[..magic.len()] != magic{
+        return Err(DecodeError::BadMagic);
+    }
+    if data.len() < magic.len() + 4 + 8{
+        return Err(DecodeError::Truncated);
+    }
+    let (body, checksum) = data.split_at(data.len() - 8);
+    let mut reader = Reader{data: body, pos: magic.len()};
+    let version = reader.u32()?;
+    if version != FORMAT_VERSION{
+        return Err(DecodeError::UnsupportedVersion(version));
+    }
+    if fnv1a(body) != u64::from_le_bytes(checksum.try_into().unwrap()){
+        return Err(DecodeError::ChecksumMismatch);
+    }
+    let rule_len = reader.u8()? as usize;
+    let rule = String::from_utf8_lossy(reader.bytes(rule_len)?).to_string();
+    if rule != RULE{
+        return Err(DecodeError::UnsupportedRule(rule));
+    }
+    Ok(reader)
+}
+pub(crate) struct Placement{
+    pub(crate) offset: Point,
+    pub(crate) depth: u64,
+    pub(crate) age: u64,
+}
+pub(crate) fn read_placement(reader: &mut Reader) -> Result<Placement, DecodeError>{
+    let offset = Point{x: reader.i64()?, y: reader.i64()?};
+    let depth = reader.u64()?;
+    let age = reader.u64()?;
+    if !(2..=MAX_DEPTH).contains(&depth){
+        return Err(DecodeError::InvalidTree(format!("depth {} out of range", depth)));
+    }
+    let size = 8i64 << depth;
+    if offset.x.checked_add(size).is_none() || offset.y.checked_add(size).is_none(){
+        return Err(DecodeError::InvalidTree("root extends past the coordinate range".to_string()));
+    }
+    Ok(Placement{offset, depth, age})
+}
+// checked before allocating anything so a bogus count cannot exhaust memory
+pub(crate) fn check_node_count(reader: &Reader, count: u64, trailing: usize) -> Result<(), DecodeError>{
+    match (count as usize).checked_mul(NODE_SIZE).and_then(|len|len.checked_add(trailing)){
+        Some(len) if len == reader.remaining() => Ok(()),
+        Some(len) if len < reader.remaining() => Err(DecodeError::TrailingData),
+        _ => Err(DecodeError::Truncated),
+    }
+}
+// reads one node, adds it to `tree` and records its key and level in `keys`
+pub(crate) fn read_node(reader: &mut Reader, tree: &mut TreeData, keys: &mut Vec<(u128, u64)>, max_level: u64) -> Result<(), DecodeError>{
+    let i = keys.len();
+    let level = reader.u8()? as u64;
+    if level == 0 || level > max_level{
+        return Err(DecodeError::InvalidTree(format!("node {} has level {}", i, level)));
+    }
+    let mut children = [0u128;4];
+    for child in children.iter_mut(){
+        let value = reader.u64()?;
+        *child = if level == 1{
+            value as u128
+        }
+        else{
+            // a key from another tree would not be in the map
+            match keys.get(value as usize){
+                Some((key, child_level)) if *child_level == level-1 && tree.map.get(*key).is_some() => *key,
+                _ => return Err(DecodeError::InvalidTree(format!("node {} has an invalid child reference {}", i, value))),
+            }
+        };
+    }
+    let key = tree.add_array(children);
+    debug_assert!(!node_is_raw(key));
+    keys.push((key, level));
+    Ok(())
+}
+
+impl TreeData{
+    // writes every node under `key` that has no index yet, children first, giving each the next index
+    pub(crate) fn write_nodes(&self, key: u128, level: u64, indexes: &mut HashMap<u128, u64>, res: &mut Vec<u8>){
+        if indexes.contains_key(&key){
+            return;
+        }
+        let children = self.children(key);
+        if level > 1{
+            for child in children.iter(){
+                self.write_nodes(*child, level-1, indexes, res);
             }
         }
-        Some(res)
+        res.push(level as u8);
+        for child in children.iter(){
+            let value = if level == 1 {*child as u64} else {indexes[child]};
+            res.extend_from_slice(&value.to_le_bytes());
+        }
+        indexes.insert(key, indexes.len() as u64);
+    }
+    pub fn serialize_treerepr(&self) -> Vec<u8>{
+        let mut nodes: Vec<u8> = Vec::new();
+        let mut indexes: HashMap<u128, u64> = HashMap::new();
+        self.write_nodes(self.root, self.depth, &mut indexes, &mut nodes);
+
+        let mut res: Vec<u8> = Vec::with_capacity(nodes.len() + 64);
+        write_preamble(&mut res, MAGIC);
+        write_placement(&mut res, self);
+        res.extend_from_slice(&(indexes.len() as u64).to_le_bytes());
+        res.extend_from_slice(&nodes);
+        seal(&mut res);
+        res
+    }
+    pub fn deserialize_treerepr(data: &[u8]) -> Result<TreeData, DecodeError>{
+        let mut reader = open_blob(data, MAGIC)?;
+        let placement = read_placement(&mut reader)?;
+        let count = reader.u64()?;
+        if count == 0{
+            return Err(DecodeError::InvalidTree("no root node".to_string()));
+        }
+        check_node_count(&reader, count, 0)?;
+
+        let mut tree = TreeData::new();
+        let mut keys: Vec<(u128, u64)> = Vec::with_capacity(count as usize);
+        for _ in 0..count{
+            read_node(&mut reader, &mut tree, &mut keys, placement.depth)?;
+        }
+        let (root, root_level) = keys[keys.len()-1];
+        if root_level != placement.depth{
+            return Err(DecodeError::InvalidTree(format!("root has level {}, expected {}", root_level, placement.depth)));
+        }
+        tree.root = root;
+        tree.depth = placement.depth;
+        tree.offset = placement.offset;
+        tree.age = placement.age;
+        Ok(tree.pruned_tree())
     }
 }
 
@@ -35,20 +253,60 @@ pub fn deserialize_transmutable<'a,T:Copy + Default>(dataiter: &mut core::slice:
 mod tests {
     // Note this useful idiom: importing names from outer (for mod tests) scope.
     use super::*;
-    
-    fn make_vec_from<T:Copy>(x:T)->Vec<u8>{
-        let mut res: Vec<u8> = Vec::new();
-        serialize_transmutable(&mut res, x);
-        res
+
+    fn glider_tree() -> TreeData{
+        let points = vec![Point{x:1, y:0}, Point{x:2, y:1}, Point{x:0, y:2}, Point{x:1, y:2}, Point{x:2, y:2}];
+        let mut tree = TreeData::gather_all_points(&points);
+        tree.step_forward(37);
+        tree
+    }
+    #[test]
+    fn test_round_trip() {
+        let tree = glider_tree();
+        let data = tree.serialize_treerepr();
+        let loaded = TreeData::deserialize_treerepr(&data).unwrap();
+        let mut expected = tree.dump_all_points();
+        let mut actual = loaded.dump_all_points();
+        expected.sort();
+        actual.sort();
+        assert_eq!(actual, expected);
+        assert_eq!(loaded.get_age(), tree.get_age());
+        // the encoding depends only on the tree, not on the machine or hash table state
+        assert_eq!(loaded.serialize_treerepr(), data);
+    }
+    #[test]
+    fn test_header_layout() {
+        let data = TreeData::new().serialize_treerepr();
+        assert_eq!(&data[..4], b"HLQT");
+        assert_eq!(&data[4..8], &[1, 0, 0, 0]);
+        assert_eq!(&data[8..15], b"\x06B3/S23");
     }
     #[test]
-    fn test_serialize() {
-        let val: u64 = 0xfc;
-        assert_eq!(deserialize_transmutable::<u64>(&mut make_vec_from(val).iter()).unwrap(), val);
+    fn test_rejects_bad_input() {
+        let data = glider_tree().serialize_treerepr();
+        assert_eq!(TreeData::deserialize_treerepr(b"").err(), Some(DecodeError::BadMagic));
+        assert_eq!(TreeData::deserialize_treerepr(b"nope, not a tree").err(), Some(DecodeError::BadMagic));
+        for len in 4..data.len(){
+            assert!(TreeData::deserialize_treerepr(&data[..len]).is_err());
+        }
+        for i in 0..data.len(){
+            let mut corrupt = data.clone();
+            corrupt[i] ^= 0x10;
+            assert!(TreeData::deserialize_treerepr(&corrupt).is_err());
+        }
+        let mut newer = data.clone();
+        newer[4] = 2;
+        assert_eq!(TreeData::deserialize_treerepr(&newer).err(), Some(DecodeError::UnsupportedVersion(2)));
     }
     #[test]
-    fn test_serialize_tuple() {
-        let val: (u64,u8,u64) = (0xffc,0xf,0xccc);
-        assert_eq!(deserialize_transmutable::<(u64,u8,u64)>(&mut make_vec_from(val).iter()).unwrap(), val);
+    fn test_rejects_forged_nodes() {
+        // a correct checksum over nonsense must still not produce a tree
+        let mut data = TreeData::new().serialize_treerepr();
+        data.truncate(data.len() - 8);
+        let last_node = data.len() - NODE_SIZE;
+        data[last_node + 1] = 5;
+        let checksum = fnv1a(&data);
+        data.extend_from_slice(&checksum.to_le_bytes());
+        assert!(matches!(TreeData::deserialize_treerepr(&data), Err(DecodeError::InvalidTree(_))));
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,262 @@
+/*
+Reproducible random soups for soup searches.
+
+The cells come from a stream of SHA-256 digests: the first is the digest
+of the seed string, every following one the digest of the one before. At
+the default density of one half every cell takes one bit, row by row with
+the most significant bit first, so a 16x16 C1 soup is the same one apgsearch
+and Catagolue build from that seed. Other densities take 16 bits per cell.
+
+Symmetric soups are the union of the random square at (0, 0) and its images
+under the symmetry group. With a diagonal mirror only the cells on or above
+the diagonal, x >= y, are drawn and the rest of the square is their image,
+so that every cell keeps the density asked for. The number after the symmetry says where the
+centre is: `1` on a cell, `2` on the middle of a cell edge, `4` on a cell
+corner, `x` marks mirrors along the diagonals.
+*/
+
+use std::fmt;
+use std::str::FromStr;
+
+use crate::point::Point;
+use crate::quadtree::TreeData;
+use crate::sha256::sha256;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Symmetry{
+    C1,
+    C2_1,
+    C2_2,
+    C2_4,
+    C4_1,
+    C4_4,
+    D2Plus1,
+    D2Plus2,
+    D2X,
+    D4Plus1,
+    D4Plus2,
+    D4Plus4,
+    D4X1,
+    D4X4,
+    D8_1,
+    D8_4,
+}
+
+const SYMMETRY_NAMES: [(Symmetry, &str); 16] = [
+    (Symmetry::C1, "C1"),
+    (Symmetry::C2_1, "C2_1"),
+    (Symmetry::C2_2, "C2_2"),
+    (Symmetry::C2_4, "C2_4"),
+    (Symmetry::C4_1, "C4_1"),
+    (Symmetry::C4_4, "C4_4"),
+    (Symmetry::D2Plus1, "D2_+1"),
+    (Symmetry::D2Plus2, "D2_+2"),
+    (Symmetry::D2X, "D2_x"),
+    (Symmetry::D4Plus1, "D4_+1"),
+    (Symmetry::D4Plus2, "D4_+2"),
+    (Symmetry::D4Plus4, "D4_+4"),
+    (Symmetry::D4X1, "D4_x1"),
+    (Symmetry::D4X4, "D4_x4"),
+    (Symmetry::D8_1, "D8_1"),
+    (Symmetry::D8_4, "D8_4"),
+];
+
+impl fmt::Display for Symmetry{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result{
+        let name = SYMMETRY_NAMES.iter().find(|(s, _)|s == self).unwrap().1;
+        write!(f, "{}", name)
+    }
+}
+
+impl FromStr for Symmetry{
+    type Err = String;
+    fn from_str(name: &str) -> Result<Symmetry, String>{
+        SYMMETRY_NAMES.iter().find(|(_, n)|*n == name).map(|(s, _)|*s)
+            .ok_or_else(||format!("unknown symmetry '{}'", name))
+    }
+}
+
+// rotations by a quarter turn and reflections, about the cell at the origin
+// (shift 0) or about the corner at its top left (shift 1)
+fn rot90(p: Point, shift: i64) -> Point{
+    Point{x: -shift - p.y, y: p.x}
+}
+fn rot180(p: Point, shift_x: i64, shift_y: i64) -> Point{
+    Point{x: -shift_x - p.x, y: -shift_y - p.y}
+}
+fn flip_x(p: Point, shift: i64) -> Point{
+    Point{x: -shift - p.x, y: p.y}
+}
+fn flip_y(p: Point, shift: i64) -> Point{
+    Point{x: p.x, y: -shift - p.y}
+}
+fn swap_xy(p: Point) -> Point{
+    Point{x: p.y, y: p.x}
+}
+
+impl Symmetry{
+    // whether the cell of the random square is drawn, rather than left to the image of another
+    fn is_drawn(&self, p: Point) -> bool{
+        match self{
+            Symmetry::D2X | Symmetry::D4X1 | Symmetry::D4X4 | Symmetry::D8_1 | Symmetry::D8_4 => p.x >= p.y,
+            _ => true,
+        }
+    }
+    // every image of a cell under the symmetry group, the cell itself included
+    pub fn images(&self, p: Point) -> Vec<Point>{
+        match self{
+            Symmetry::C1 => vec![p],
+            Symmetry::C2_1 => vec![p, rot180(p, 0, 0)],
+            Symmetry::C2_2 => vec![p, rot180(p, 1, 0)],
+            Symmetry::C2_4 => vec![p, rot180(p, 1, 1)],
+            Symmetry::C4_1 | Symmetry::C4_4 => {
+                let shift = if *self == Symmetry::C4_1 {0} else {1};
+                let r1 = rot90(p, shift);
+                let r2 = rot90(r1, shift);
+                vec![p, r1, r2, rot90(r2, shift)]
+            },
+            Symmetry::D2Plus1 => vec![p, flip_y(p, 0)],
+            Symmetry::D2Plus2 => vec![p, flip_y(p, 1)],
+            Symmetry::D2X => vec![p, swap_xy(p)],
+            Symmetry::D4Plus1 => vec![p, flip_x(p, 0), flip_y(p, 0), rot180(p, 0, 0)],
+            Symmetry::D4Plus2 => vec![p, flip_x(p, 1), flip_y(p, 0), rot180(p, 1, 0)],
+            Symmetry::D4Plus4 => vec![p, flip_x(p, 1), flip_y(p, 1), rot180(p, 1, 1)],
+            Symmetry::D4X1 => vec![p, swap_xy(p), rot180(p, 0, 0), rot180(swap_xy(p), 0, 0)],
+            Symmetry::D4X4 => vec![p, swap_xy(p), rot180(p, 1, 1), rot180(swap_xy(p), 1, 1)],
+            Symmetry::D8_1 | Symmetry::D8_4 => {
+                let rotations = if *self == Symmetry::D8_1 {Symmetry::C4_1} else {Symmetry::C4_4};
+                let mut res = rotations.images(p);
+                res.extend(rotations.images(swap_xy(p)));
+                res
+            },
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct SoupParams{
+    // side of the random square
+    pub size: usize,
+    // chance of each cell of the square being alive
+    pub density: f64,
+    pub symmetry: Symmetry,
+}
+impl Default for SoupParams{
+    fn default() -> SoupParams{
+        SoupParams{size: 16, density: 0.5, symmetry: Symmetry::C1}
+    }
+}
+
+struct DigestStream{
+    digest: [u8; 32],
+    pos: usize,
+}
+impl DigestStream{
+    fn new(seed: &str) -> DigestStream{
+        DigestStream{digest: sha256(seed.as_bytes()), pos: 0}
+    }
+    fn next_byte(&mut self) -> u8{
+        if self.pos == self.digest.len(){
+            self.digest = sha256(&self.digest);
+            self.pos = 0;
+        }
+        self.pos += 1;
+        self.digest[self.pos - 1]
+    }
+}
+
+// live cells of the soup grown from `seed`
+pub fn soup_points(seed: &str, params: &SoupParams) -> Vec<Point>{
+    let mut stream = DigestStream::new(seed);
+    let cells = (0..params.size * params.size)
+        .map(|i|Point{x: (i % params.size) as i64, y: (i / params.size) as i64})
+        .filter(|p|params.symmetry.is_drawn(*p));
+    let mut square = Vec::new();
+    if params.density == 0.5{
+        let mut byte = 0;
+        for (i, p) in cells.enumerate(){
+            if i % 8 == 0{
+                byte = stream.next_byte();
+            }
+            if byte & (0x80 >> (i % 8)) != 0{
+                square.push(p);
+            }
+        }
+    }
+    else{
+        let threshold = (params.density.clamp(0.0, 1.0) * 65536.0) as u32;
+        for p in cells{
+            let value = u16::from_be_bytes([stream.next_byte(), stream.next_byte()]) as u32;
+            if value < threshold{
+                square.push(p);
+            }
+        }
+    }
+    let mut points: Vec<Point> = square.iter().flat_map(|p|params.symmetry.images(*p)).collect();
+    points.sort();
+    points.dedup();
+    points
+}
+
+impl TreeData{
+    pub fn random_soup(seed: &str, params: &SoupParams) -> TreeData{
+        TreeData::gather_all_points(&soup_points(seed, params))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    // Note this useful idiom: importing names from outer (for mod tests) scope.
+    use super::*;
+    use std::collections::HashSet;
+
+    #[test]
+    fn test_c1_soup_bits() {
+        // the first two digest bytes make up the top row, most significant bit on the left
+        let digest = sha256(b"k_test0");
+        let points = soup_points("k_test0", &SoupParams::default());
+        let row0: Vec<i64> = points.iter().filter(|p|p.y == 0).map(|p|p.x).collect();
+        let expected: Vec<i64> = (0..16).filter(|x|digest[(x / 8) as usize] & (0x80 >> (x % 8)) != 0).collect();
+        assert_eq!(row0, expected);
+        assert!(points.iter().all(|p|(0..16).contains(&p.x) && (0..16).contains(&p.y)));
+        assert_eq!(points, soup_points("k_test0", &SoupParams::default()));
+        assert_ne!(points, soup_points("k_test1", &SoupParams::default()));
+    }
+    #[test]
+    fn test_density() {
+        let params = SoupParams{size: 64, density: 0.1, symmetry: Symmetry::C1};
+        let count = soup_points("density", &params).len();
+        assert!((300..520).contains(&count), "{} cells", count);
+        let full = SoupParams{size: 8, density: 1.0, symmetry: Symmetry::C1};
+        assert_eq!(soup_points("full", &full).len(), 64);
+    }
+    #[test]
+    fn test_diagonal_density() {
+        // a cell and its mirror image are one draw, so cells off the diagonal keep the density
+        for density in [0.5, 0.25]{
+            let params = SoupParams{size: 64, density, symmetry: Symmetry::D2X};
+            let points = soup_points("diagonal", &params);
+            let count = points.iter().filter(|p|p.x != p.y && p.x >= 0 && p.y >= 0).count() as f64;
+            let expected = density * (64.0 * 63.0);
+            assert!((count - expected).abs() < 0.1 * expected, "{} cells for {}", count, expected);
+        }
+    }
+    #[test]
+    fn test_symmetries_hold() {
+        for (symmetry, name) in SYMMETRY_NAMES.iter(){
+            assert_eq!(name.parse::<Symmetry>().unwrap(), *symmetry);
+            assert_eq!(symmetry.to_string(), *name);
+            let points = soup_points("sym", &SoupParams{size: 16, density: 0.5, symmetry: *symmetry});
+            let set: HashSet<Point> = points.iter().copied().collect();
+            for p in points.iter(){
+                for image in symmetry.images(*p){
+                    assert!(set.contains(&image), "{} not closed under its images", name);
+                }
+            }
+        }
+        assert!("C3".parse::<Symmetry>().is_err());
+        // the centre of C2_4 is a cell corner, that of C2_1 a cell
+        assert_eq!(Symmetry::C2_4.images(Point{x:0, y:0})[1], Point{x:-1, y:-1});
+        assert_eq!(Symmetry::C2_1.images(Point{x:0, y:0})[1], Point{x:0, y:0});
+    }
+}
@@ -0,0 +1,181 @@
+/*
+Detection of the generation at which a pattern stabilises, i.e. when all
+that is left is periodic, apart from gliders and spaceships flying away.
+
+A generation counts as stable when the population from there on repeats
+with a short period, both right away and again after a long hashlife jump.
+Escaping spaceships keep their population, so they do not get in the way.
+The first stable generation is found by doubling jumps followed by
+bisection, so the pattern is never stepped one generation at a time
+through its whole history.
+
+The period is then measured on the region around the stabilised pattern,
+once the escaping spaceships have had time to leave it.
+*/
+
+use crate::frame::{node_size, WindowCache};
+use crate::point::Point;
+use crate::quadtree::TreeData;
+
+// room left around the bounding box for oscillators that are larger in other phases
+const CORE_MARGIN: i64 = 16;
+// shortest jump used to check the population stays periodic
+const MIN_LOOKAHEAD: u64 = 1024;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Stabilisation{
+    pub generation: u64,
+    pub population: u64,
+    pub period: u64,
+}
+
+fn population_period(pops: &[u64], max_period: u64) -> Option<u64>{
+    (1..=max_period).find(|p|{
+        let p = *p as usize;
+        (0..pops.len()-p).all(|k|pops[k] == pops[k+p])
+    })
+}
+
+impl TreeData{
+    pub(crate) fn populations(&mut self, count: u64) -> Vec<u64>{
+        let mut res = Vec::with_capacity(count as usize);
+        for i in 0..count{
+            if i != 0{
+                self.step_forward(1);
+            }
+            res.push(self.num_live_cells());
+        }
+        res
+    }
+    // the square that is left with only the stabilised pattern in it, once spaceships have left
+    fn core_frame(&self) -> (Point, u64){
+        let (min, max) = self.bounding_box().unwrap_or_default();
+        let extent = std::cmp::max(max.x - min.x, max.y - min.y) + 1 + 2*CORE_MARGIN;
+        let mut depth = 0;
+        while node_size(depth) < extent{
+            depth += 1;
+        }
+        (min + Point{x: -CORE_MARGIN, y: -CORE_MARGIN}, depth)
+    }
+    fn lookahead(depth: u64, period: u64, min_jump: u64) -> u64{
+        // far enough for anything at c/10 or faster to leave the core frame
+        let min_jump = MIN_LOOKAHEAD.max(16*node_size(depth) as u64).max(min_jump);
+        min_jump.div_ceil(period) * period
+    }
+    // None once the trees checked grow past `max_nodes` nodes
+    fn is_stable(&self, max_period: u64, max_nodes: usize) -> Option<bool>{
+        let mut tree = self.pruned_tree();
+        let (_, depth) = tree.core_frame();
+        let pops = tree.populations(3*max_period);
+        let period = match population_period(&pops, max_period){
+            Some(period) => period,
+            None => return Some(false),
+        };
+        let jump = TreeData::lookahead(depth, period, 3*max_period);
+        tree.step_forward(jump - (3*max_period - 1));
+        if tree.hash_count() > max_nodes{
+            return None;
+        }
+        let later_pops = tree.populations(period);
+        Some(later_pops[..] == pops[..period as usize])
+    }
+    fn core_period(&self, max_period: u64) -> Option<u64>{
+        let mut tree = self.pruned_tree();
+        let (top_left, depth) = tree.core_frame();
+        tree.step_forward(TreeData::lookahead(depth, 1, 0));
+        let mut cache = WindowCache::new();
+        let start = tree.frame_key(top_left, depth, &mut cache);
+        for period in 1..=max_period{
+            tree.step_forward(1);
+            if tree.frame_key(top_left, depth, &mut cache) == start{
+                return Some(period);
+            }
+        }
+        None
+    }
+    // finds the first generation within `max_generations` from which the pattern
+    // only repeats itself with a period of at most `max_period`, ignoring escaping
+    // gliders and spaceships. The tree itself is not changed.
+    pub fn find_stabilisation(&self, max_generations: u64, max_period: u64) -> Option<Stabilisation>{
+        self.find_stabilisation_within(max_generations, max_period, usize::MAX)
+    }
+    // as find_stabilisation, but also gives up once any of the trees stepped
+    // holds more than `max_nodes` nodes
+    pub fn find_stabilisation_within(&self, max_generations: u64, max_period: u64, max_nodes: usize) -> Option<Stabilisation>{
+        assert!(max_period > 0);
+        // jump ahead in doubling steps until a stable generation is reached
+        let mut unstable = self.pruned_tree();
+        let mut stable = if self.is_stable(max_period, max_nodes)? {Some(self.pruned_tree())} else {None};
+        let mut jump = 64;
+        while stable.is_none(){
+            let gen = unstable.get_age() - self.get_age();
+            if gen >= max_generations{
+                return None;
+            }
+            let mut next = unstable.pruned_tree();
+            next.step_forward(std::cmp::min(jump, max_generations - gen));
+            if next.hash_count() > max_nodes{
+                return None;
+            }
+            if next.is_stable(max_period, max_nodes)?{
+                stable = Some(next);
+            }
+            else{
+                unstable = next;
+                jump *= 2;
+            }
+        }
+        let mut stable = stable.unwrap();
+        // bisect between the last unstable and the first stable generation found
+        while stable.get_age() > unstable.get_age() + 1{
+            let mut mid = unstable.pruned_tree();
+            mid.step_forward((stable.get_age() - unstable.get_age()) / 2);
+            if mid.is_stable(max_period, max_nodes)?{
+                stable = mid;
+            }
+            else{
+                unstable = mid;
+            }
+        }
+        let period = stable.core_period(max_period)?;
+        Some(Stabilisation{
+            generation: stable.get_age(),
+            population: stable.num_live_cells(),
+            period,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    // Note this useful idiom: importing names from outer (for mod tests) scope.
+    use super::*;
+    use crate::rle::parse_rle;
+
+    fn stabilise(rle: &str, max_generations: u64) -> Option<Stabilisation>{
+        TreeData::from_pattern(&parse_rle(rle).unwrap()).find_stabilisation(max_generations, 4)
+    }
+    #[test]
+    fn test_still_life_is_stable() {
+        assert_eq!(stabilise("x = 2, y = 2\n2o$2o!\n", 100),
+            Some(Stabilisation{generation: 0, population: 4, period: 1}));
+        assert_eq!(stabilise("x = 3, y = 1\n3o!\n", 100),
+            Some(Stabilisation{generation: 0, population: 3, period: 2}));
+    }
+    #[test]
+    fn test_b_heptomino() {
+        // stabilises at generation 148 into three blocks, a ship and two escaping gliders,
+        // 12 + 6 + 10 cells
+        let result = stabilise("x = 4, y = 3\nob2o$3ob$bo!\n", 1000).unwrap();
+        assert_eq!(result, Stabilisation{generation: 148, population: 28, period: 1});
+        assert_eq!(stabilise("x = 4, y = 3\nob2o$3ob$bo!\n", 100), None);
+        let tree = TreeData::from_pattern(&parse_rle("x = 4, y = 3\nob2o$3ob$bo!\n").unwrap());
+        assert_eq!(tree.find_stabilisation_within(1000, 4, 20), None);
+    }
+    #[test]
+    fn test_dies_out() {
+        // diehard vanishes after 130 generations
+        let result = stabilise("x = 8, y = 3\n6bo$2o$bo3b3o!\n", 1000).unwrap();
+        assert_eq!(result, Stabilisation{generation: 130, population: 0, period: 1});
+    }
+}
@@ -0,0 +1,118 @@
+/*
+Time series of the population, bounding box and node count of a universe,
+for charting growth. They are written as CSV, with the bounding box left
+blank while the universe is empty, or as JSON Lines.
+*/
+
+use crate::point::Point;
+use crate::quadtree::TreeData;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Sample{
+    pub generation: u64,
+    pub population: u64,
+    // inclusive corners, None when there are no live cells
+    pub bounding_box: Option<(Point, Point)>,
+    // nodes held in the hash table, including ones no longer reachable from the root
+    pub node_count: usize,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct TimeSeries{
+    pub samples: Vec<Sample>,
+}
+
+impl TimeSeries{
+    pub fn new() -> TimeSeries{
+        TimeSeries::default()
+    }
+    pub fn record(&mut self, tree: &TreeData){
+        self.samples.push(tree.sample());
+    }
+    // records the tree if the series samples its generation: the first one, every `cadence`
+    // generations after it, and the last one, `end`
+    pub fn record_due(&mut self, tree: &TreeData, cadence: u64, end: u64){
+        let due = match self.samples.first(){
+            Some(first) => tree.age == end || (tree.age - first.generation).is_multiple_of(cadence),
+            None => true,
+        };
+        if due{
+            self.record(tree);
+        }
+    }
+    pub fn to_csv(&self) -> String{
+        let mut s = String::from("generation,population,min_x,min_y,max_x,max_y,node_count\n");
+        for sample in self.samples.iter(){
+            let bbox = match sample.bounding_box{
+                Some((min, max)) => format!("{},{},{},{}", min.x, min.y, max.x, max.y),
+                None => ",,,".to_string(),
+            };
+            s.push_str(format!("{},{},{},{}\n", sample.generation, sample.population, bbox, sample.node_count).as_str());
+        }
+        s
+    }
+    pub fn to_jsonl(&self) -> String{
+        let mut s = String::new();
+        for sample in self.samples.iter(){
+            let bbox = match sample.bounding_box{
+                Some((min, max)) => format!("[{},{},{},{}]", min.x, min.y, max.x, max.y),
+                None => "null".to_string(),
+            };
+            s.push_str(format!("{{\"generation\":{},\"population\":{},\"bounding_box\":{},\"node_count\":{}}}\n",
+                sample.generation, sample.population, bbox, sample.node_count).as_str());
+        }
+        s
+    }
+}
+
+impl TreeData{
+    pub fn sample(&self) -> Sample{
+        Sample{
+            generation: self.age,
+            population: self.num_live_cells(),
+            bounding_box: self.bounding_box(),
+            node_count: self.hash_count(),
+        }
+    }
+    // runs the universe for `generations`, sampling every `cadence` generations starting with the
+    // current one, and the last generation
+    pub fn record_series(&mut self, generations: u64, cadence: u64) -> TimeSeries{
+        assert!(cadence > 0);
+        let mut series = TimeSeries::new();
+        let end = self.age + generations;
+        series.record_due(self, cadence, end);
+        while self.age < end{
+            self.step_forward(std::cmp::min(cadence, end - self.age));
+            series.record_due(self, cadence, end);
+        }
+        series
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    // Note this useful idiom: importing names from outer (for mod tests) scope.
+    use super::*;
+    use crate::rle::parse_rle;
+
+    #[test]
+    fn test_series() {
+        let mut glider = TreeData::from_pattern(&parse_rle("x = 3, y = 3\nbo$2bo$3o!\n").unwrap());
+        let series = glider.record_series(10, 4);
+        assert_eq!(glider.get_age(), 10);
+        let gens: Vec<u64> = series.samples.iter().map(|s|s.generation).collect();
+        assert_eq!(gens, vec![0, 4, 8, 10]);
+        assert!(series.samples.iter().all(|s|s.population == 5));
+        assert_eq!(series.samples[2].bounding_box, Some((Point{x: 2, y: 2}, Point{x: 4, y: 4})));
+        let csv = series.to_csv();
+        assert_eq!(csv.lines().count(), 5);
+        assert_eq!(glider.record_series(8, 4).samples.len(), 3);
+        assert!(csv.lines().nth(2).unwrap().starts_with("4,5,1,1,3,3,"));
+        let jsonl = series.to_jsonl();
+        assert!(jsonl.lines().next().unwrap().starts_with("{\"generation\":0,\"population\":5,\"bounding_box\":[0,0,2,2],"));
+        let mut empty = TimeSeries::new();
+        empty.record(&TreeData::new());
+        assert!(empty.to_csv().lines().nth(1).unwrap().starts_with("0,0,,,,,"));
+        assert!(empty.to_jsonl().contains("\"bounding_box\":null"));
+    }
+}
@@ -0,0 +1,299 @@
+/*
+Finding every occurrence of a small template in a universe.
+
+The template is turned into variants, one for each distinct image of it
+over the phases and orientations asked for. A variant matches where the
+cells of a window of its size are exactly its cells; with a dead border
+the window is one cell larger on every side and the extra ring has to be
+empty.
+
+Matches are found per node and memoised by node key. The matches lying
+inside a node are those inside its four children, plus those crossing a
+middle line, which lie inside one of the five half size squares centred
+on the middle lines. Each crossing match is taken from exactly one of
+them, so nothing is counted twice. Nodes smaller than four windows are
+searched directly, row by row.
+*/
+
+use std::collections::{HashMap, HashSet};
+use std::rc::Rc;
+
+use crate::frame::node_size;
+use crate::point::Point;
+use crate::quadtree::TreeData;
+use crate::raw_ops::{is_on_4x4_border, slice};
+use crate::transform::Transform;
+
+// widest window, border included, so that searched nodes fit in 64 bit rows
+const MAX_WINDOW: i64 = 32;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct FinderOptions{
+    // generations of the template to look for, 1 for only the template as given
+    pub phases: u64,
+    // look for all 8 rotations and reflections, rather than only the template as given
+    pub orientations: bool,
+    // require the cells around the template's bounding box to be dead
+    pub dead_border: bool,
+}
+impl Default for FinderOptions{
+    fn default() -> FinderOptions{
+        FinderOptions{phases: 1, orientations: true, dead_border: false}
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Match{
+    // top left corner of the matched image's bounding box
+    pub position: Point,
+    // generation of the template the match is an image of
+    pub phase: u64,
+    pub transform: Transform,
+}
+
+struct Variant{
+    phase: u64,
+    transform: Transform,
+    width: i64,
+    height: i64,
+    // the cells of the window, one row per element
+    rows: Vec<u64>,
+}
+
+// anchor of the window relative to the node and index of the variant
+type NodeMatches = Rc<Vec<(Point, usize)>>;
+
+pub struct PatternFinder{
+    variants: Vec<Variant>,
+    border: i64,
+    window: i64,
+    cache: HashMap<u128, NodeMatches>,
+}
+
+fn normalized(points: &[Point]) -> Vec<Point>{
+    let minx = points.iter().map(|p|p.x).min().unwrap_or(0);
+    let miny = points.iter().map(|p|p.y).min().unwrap_or(0);
+    let mut res: Vec<Point> = points.iter().map(|p|*p + Point{x: -minx, y: -miny}).collect();
+    res.sort();
+    res
+}
+
+impl PatternFinder{
+    // panics if the template is empty or, with its border, wider than 32 cells
+    pub fn new(template: &[Point], options: &FinderOptions) -> PatternFinder{
+        assert!(!template.is_empty(), "empty template");
+        let border = if options.dead_border {1} else {0};
+        let transforms: &[Transform] = if options.orientations {&Transform::ALL} else {&[Transform::Identity]};
+        let mut tree = TreeData::gather_all_points(&template.to_vec());
+        let mut seen = HashSet::new();
+        let mut variants = Vec::new();
+        for phase in 0..std::cmp::max(1, options.phases){
+            if phase != 0{
+                tree.step_forward(1);
+            }
+            let points = tree.dump_all_points();
+            if points.is_empty(){
+                break;
+            }
+            for transform in transforms.iter(){
+                let image = normalized(&points.iter().map(|p|transform.apply(*p)).collect::<Vec<Point>>());
+                if !seen.insert(image.clone()){
+                    continue;
+                }
+                let width = image.iter().map(|p|p.x).max().unwrap() + 1 + 2*border;
+                let height = image.iter().map(|p|p.y).max().unwrap() + 1 + 2*border;
+                assert!(width <= MAX_WINDOW && height <= MAX_WINDOW, "template too large to search for");
+                let mut rows = vec![0u64; height as usize];
+                for p in image.iter(){
+                    rows[(p.y + border) as usize] |= 1 << (p.x + border);
+                }
+                variants.push(Variant{phase, transform: *transform, width, height, rows});
+            }
+        }
+        let window = variants.iter().map(|v|std::cmp::max(v.width, v.height)).max().unwrap();
+        PatternFinder{variants, border, window, cache: HashMap::new()}
+    }
+    fn fill_rows(tree: &TreeData, key: u128, depth: u64, origin: Point, rows: &mut [u64]){
+        if tree.is_empty_node(key, depth){
+            return;
+        }
+        if depth == 0{
+            for y in 0..8{
+                rows[(origin.y + y) as usize] |= (((key as u64) >> (8*y)) & 0xff) << origin.x;
+            }
+            return;
+        }
+        let half = node_size(depth - 1);
+        for (i, child) in tree.children(key).iter().enumerate(){
+            let shift = Point{x: (i as i64 % 2)*half, y: (i as i64 / 2)*half};
+            PatternFinder::fill_rows(tree, *child, depth - 1, origin + shift, rows);
+        }
+    }
+    // matches in a node too small to split, found by sliding every window over its rows
+    fn scan_node(&self, tree: &TreeData, key: u128, depth: u64) -> Vec<(Point, usize)>{
+        let size = node_size(depth);
+        let mut rows = vec![0u64; size as usize];
+        PatternFinder::fill_rows(tree, key, depth, Point{x: 0, y: 0}, &mut rows);
+        let mut res = Vec::new();
+        for (index, variant) in self.variants.iter().enumerate(){
+            let mask = (1u64 << variant.width) - 1;
+            for y in 0..=size - variant.height{
+                for x in 0..=size - variant.width{
+                    let matches = variant.rows.iter().enumerate()
+                        .all(|(r, row)|(rows[(y + r as i64) as usize] >> x) & mask == *row);
+                    if matches{
+                        res.push((Point{x, y}, index));
+                    }
+                }
+            }
+        }
+        res
+    }
+    fn node_matches(&mut self, tree: &mut TreeData, key: u128, depth: u64) -> NodeMatches{
+        if let Some(res) = self.cache.get(&key){
+            return res.clone();
+        }
+        let size = node_size(depth);
+        let res = if tree.is_empty_node(key, depth){
+            Vec::new()
+        }
+        else if size < 4*self.window || depth < 2{
+            self.scan_node(tree, key, depth)
+        }
+        else{
+            let (quarter, half) = (size / 4, size / 2);
+            // which of the 0, quarter and half offsets a window starting at `a` belongs to
+            let owner = |a: i64, len: i64| if a < half && a + len > half {quarter} else if a < half {0} else {half};
+            let grid = tree.grandchildren(key);
+            let mut res = Vec::new();
+            for gy in 0..3{
+                for gx in 0..3{
+                    let sub = tree.add_array(slice(&grid, gx, gy));
+                    let shift = Point{x: gx as i64 * quarter, y: gy as i64 * quarter};
+                    for (anchor, index) in self.node_matches(tree, sub, depth - 1).iter(){
+                        let anchor = *anchor + shift;
+                        let variant = &self.variants[*index];
+                        if owner(anchor.x, variant.width) == shift.x && owner(anchor.y, variant.height) == shift.y{
+                            res.push((anchor, *index));
+                        }
+                    }
+                }
+            }
+            res
+        };
+        let res = Rc::new(res);
+        self.cache.insert(key, res.clone());
+        res
+    }
+    // every occurrence of the template in the universe, sorted by position
+    pub fn find(&mut self, tree: &mut TreeData) -> Vec<Match>{
+        // leave an empty margin at least a window wide around the root
+        while node_size(tree.depth) < 4*self.window || tree.grandchildren(tree.root).iter().enumerate()
+                .any(|(i, key)|is_on_4x4_border(i) && !tree.is_empty_node(*key, tree.depth - 2)){
+            tree.increase_depth();
+        }
+        let (root, depth) = (tree.root, tree.depth);
+        let border = Point{x: self.border, y: self.border};
+        let mut res: Vec<Match> = self.node_matches(tree, root, depth).iter().map(|(anchor, index)|{
+            let variant = &self.variants[*index];
+            Match{position: tree.offset + *anchor + border, phase: variant.phase, transform: variant.transform}
+        }).collect();
+        res.sort();
+        res
+    }
+}
+
+impl TreeData{
+    pub fn find_pattern(&mut self, template: &[Point], options: &FinderOptions) -> Vec<Match>{
+        PatternFinder::new(template, options).find(self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    // Note this useful idiom: importing names from outer (for mod tests) scope.
+    use super::*;
+    use crate::rle::parse_rle;
+
+    fn glider() -> Vec<Point>{
+        parse_rle("x = 3, y = 3\nbo$2bo$3o!\n").unwrap().points
+    }
+    fn placed(points: &[Point], transform: Transform, at: Point) -> Vec<Point>{
+        let image = normalized(&points.iter().map(|p|transform.apply(*p)).collect::<Vec<Point>>());
+        image.iter().map(|p|*p + at).collect()
+    }
+    #[test]
+    fn test_find_orientations() {
+        let mut cells = placed(&glider(), Transform::Identity, Point{x: -40, y: 3});
+        cells.extend(placed(&glider(), Transform::Rotate90, Point{x: 60, y: -70}));
+        cells.extend(placed(&glider(), Transform::FlipHorizontal, Point{x: 7, y: 7}));
+        // a block is not a glider
+        cells.extend(placed(&[Point{x:0, y:0}, Point{x:1, y:0}, Point{x:0, y:1}, Point{x:1, y:1}], Transform::Identity, Point{x: 30, y: 30}));
+        let mut tree = TreeData::gather_all_points(&cells);
+        let found = tree.find_pattern(&glider(), &FinderOptions::default());
+        let positions: Vec<Point> = found.iter().map(|m|m.position).collect();
+        assert_eq!(positions, vec![Point{x: 60, y: -70}, Point{x: -40, y: 3}, Point{x: 7, y: 7}]);
+        let plain = tree.find_pattern(&glider(), &FinderOptions{orientations: false, ..FinderOptions::default()});
+        assert_eq!(plain, vec![Match{position: Point{x: -40, y: 3}, phase: 0, transform: Transform::Identity}]);
+    }
+    #[test]
+    fn test_phases_and_border() {
+        let mut cells = glider();
+        // a glider two generations on, with a cell touching its bounding box
+        let mut later = TreeData::gather_all_points(&glider());
+        later.step_forward(2);
+        let later_cells = normalized(&later.dump_all_points());
+        cells.extend(later_cells.iter().map(|p|*p + Point{x: 20, y: 0}));
+        cells.push(Point{x: 23, y: 1});
+        let mut tree = TreeData::gather_all_points(&cells);
+        let options = FinderOptions{phases: 4, orientations: false, dead_border: false};
+        let found = tree.find_pattern(&glider(), &options);
+        assert_eq!(found.iter().map(|m|(m.position, m.phase)).collect::<Vec<(Point, u64)>>(),
+            vec![(Point{x: 0, y: 0}, 0), (Point{x: 20, y: 0}, 2)]);
+        let bordered = tree.find_pattern(&glider(), &FinderOptions{dead_border: true, ..options});
+        assert_eq!(bordered.len(), 1);
+        assert_eq!(bordered[0].position, Point{x: 0, y: 0});
+    }
+    #[test]
+    fn test_repetitive_pattern() {
+        // a grid of gliders, some straddling node boundaries, searched twice with one finder
+        let mut cells = Vec::new();
+        for i in 0..12{
+            for j in 0..12{
+                cells.extend(placed(&glider(), Transform::Identity, Point{x: i*37 - 200, y: j*29 - 150}));
+            }
+        }
+        let mut finder = PatternFinder::new(&glider(), &FinderOptions{dead_border: true, ..FinderOptions::default()});
+        let mut tree = TreeData::gather_all_points(&cells);
+        assert_eq!(finder.find(&mut tree).len(), 144);
+        tree.translate(Point{x: 5, y: 3});
+        let found = finder.find(&mut tree);
+        assert_eq!(found.len(), 144);
+        assert_eq!(found[0].position, Point{x: -195, y: -147});
+    }
+    #[test]
+    fn test_against_brute_force() {
+        // isolated dominoes among scattered cells, checked cell by cell
+        let mut seed: u64 = 12345;
+        let mut cells = HashSet::new();
+        for _ in 0..3000{
+            seed = seed.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+            cells.insert(Point{x: (seed >> 33) as i64 % 300 - 150, y: (seed >> 13) as i64 % 300 - 150});
+        }
+        let live = |x: i64, y: i64|cells.contains(&Point{x, y});
+        let mut expected = Vec::new();
+        for p in cells.iter(){
+            let is_domino = (-1..=1).all(|dy|(-1..=2).all(|dx|live(p.x + dx, p.y + dy) == (dy == 0 && (dx == 0 || dx == 1))));
+            if is_domino{
+                expected.push(*p);
+            }
+        }
+        expected.sort();
+        let domino = [Point{x: 0, y: 0}, Point{x: 1, y: 0}];
+        let options = FinderOptions{phases: 1, orientations: false, dead_border: true};
+        let mut tree = TreeData::gather_all_points(&cells.iter().copied().collect());
+        let found: Vec<Point> = tree.find_pattern(&domino, &options).iter().map(|m|m.position).collect();
+        assert!(expected.len() > 10);
+        assert_eq!(found, expected);
+    }
+}
@@ -0,0 +1,165 @@
+/*
+Rotations, reflections and translations of a whole universe.
+
+A rotation or reflection maps every node onto a node of the same size
+with its children moved around and each of them transformed in turn, so
+it is computed on the node DAG with one result memoised per distinct
+node, and the offset of the root is moved to where the transformed
+square lands. Translation only moves the offset.
+
+Transforms act on the plane about the cell at the origin, with y pointing
+down, so `Rotate90` turns the pattern clockwise as it is displayed.
+*/
+
+use std::collections::HashMap;
+
+use crate::point::Point;
+use crate::quadtree::TreeData;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Transform{
+    Identity,
+    Rotate90,
+    Rotate180,
+    Rotate270,
+    // mirror left to right
+    FlipHorizontal,
+    // mirror top to bottom
+    FlipVertical,
+    // swap x and y
+    FlipDiagonal,
+    // swap x and y and negate both
+    FlipAntiDiagonal,
+}
+
+impl Transform{
+    pub const ALL: [Transform; 8] = [
+        Transform::Identity,
+        Transform::Rotate90,
+        Transform::Rotate180,
+        Transform::Rotate270,
+        Transform::FlipHorizontal,
+        Transform::FlipVertical,
+        Transform::FlipDiagonal,
+        Transform::FlipAntiDiagonal,
+    ];
+    // the rows of the matrix mapping (x, y) to (x', y')
+    fn matrix(&self) -> [i64; 4]{
+        match self{
+            Transform::Identity => [1, 0, 0, 1],
+            Transform::Rotate90 => [0, -1, 1, 0],
+            Transform::Rotate180 => [-1, 0, 0, -1],
+            Transform::Rotate270 => [0, 1, -1, 0],
+            Transform::FlipHorizontal => [-1, 0, 0, 1],
+            Transform::FlipVertical => [1, 0, 0, -1],
+            Transform::FlipDiagonal => [0, 1, 1, 0],
+            Transform::FlipAntiDiagonal => [0, -1, -1, 0],
+        }
+    }
+    pub fn apply(&self, p: Point) -> Point{
+        let [a, b, c, d] = self.matrix();
+        Point{x: a*p.x + b*p.y, y: c*p.x + d*p.y}
+    }
+    pub fn inverse(&self) -> Transform{
+        match self{
+            Transform::Rotate90 => Transform::Rotate270,
+            Transform::Rotate270 => Transform::Rotate90,
+            t => *t,
+        }
+    }
+    // maps a cell of a square of side `size` onto the same square
+    fn apply_within(&self, p: Point, size: i64) -> Point{
+        let [a, b, c, d] = self.matrix();
+        let q = self.apply(p);
+        Point{
+            x: q.x + if a < 0 || b < 0 {size - 1} else {0},
+            y: q.y + if c < 0 || d < 0 {size - 1} else {0},
+        }
+    }
+    fn apply_leaf(&self, leaf: u64) -> u64{
+        let mut res = 0;
+        let mut bits = leaf;
+        while bits != 0{
+            let i = bits.trailing_zeros() as i64;
+            bits &= bits - 1;
+            let q = self.apply_within(Point{x: i % 8, y: i / 8}, 8);
+            res |= 1 << (q.y*8 + q.x);
+        }
+        res
+    }
+}
+
+impl TreeData{
+    fn transform_node(&mut self, key: u128, depth: u64, transform: Transform, cache: &mut HashMap<u128, u128>) -> u128{
+        if depth == 0{
+            return transform.apply_leaf(key as u64) as u128;
+        }
+        if self.is_empty_node(key, depth){
+            return key;
+        }
+        if let Some(res) = cache.get(&key){
+            return *res;
+        }
+        let mut arr = [0; 4];
+        for (i, child) in self.children(key).iter().enumerate(){
+            let q = transform.apply_within(Point{x: (i % 2) as i64, y: (i / 2) as i64}, 2);
+            arr[(q.y*2 + q.x) as usize] = self.transform_node(*child, depth - 1, transform, cache);
+        }
+        let res = self.add_array(arr);
+        cache.insert(key, res);
+        res
+    }
+    // rotates or reflects the universe about the cell at the origin
+    pub fn transform(&mut self, transform: Transform){
+        if transform == Transform::Identity{
+            return;
+        }
+        let mut cache = HashMap::new();
+        self.root = self.transform_node(self.root, self.depth, transform, &mut cache);
+        let size = 8 << self.depth;
+        let a = transform.apply(self.offset);
+        let b = transform.apply(self.offset + Point{x: size - 1, y: size - 1});
+        self.offset = Point{x: std::cmp::min(a.x, b.x), y: std::cmp::min(a.y, b.y)};
+    }
+    pub fn translate(&mut self, by: Point){
+        self.offset = self.offset + by;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    // Note this useful idiom: importing names from outer (for mod tests) scope.
+    use super::*;
+    use crate::rle::parse_rle;
+
+    fn sorted(points: Vec<Point>) -> Vec<Point>{
+        let mut res = points;
+        res.sort();
+        res
+    }
+    #[test]
+    fn test_transform_matches_points() {
+        // an asymmetric pattern spread over several leaves and off the origin
+        let rle = "x = 31, y = 11\nbo$2bo$3o6$20b2o$20bobo7bo$21bo7b2o!\n";
+        let points: Vec<Point> = parse_rle(rle).unwrap().points.iter().map(|p|*p + Point{x: -7, y: 3}).collect();
+        for transform in Transform::ALL{
+            let mut tree = TreeData::gather_all_points(&points);
+            tree.transform(transform);
+            let expected = sorted(points.iter().map(|p|transform.apply(*p)).collect());
+            assert_eq!(sorted(tree.dump_all_points()), expected, "{:?}", transform);
+            tree.transform(transform.inverse());
+            assert_eq!(sorted(tree.dump_all_points()), sorted(points.clone()));
+        }
+    }
+    #[test]
+    fn test_transform_then_step() {
+        // a glider turned around still runs correctly, now heading up and left
+        let mut tree = TreeData::from_pattern(&parse_rle("x = 3, y = 3\nbo$2bo$3o!\n").unwrap());
+        tree.transform(Transform::Rotate180);
+        tree.translate(Point{x: 100, y: -50});
+        let start = sorted(tree.dump_all_points());
+        tree.step_forward(8);
+        let moved: Vec<Point> = start.iter().map(|p|*p + Point{x: -2, y: -2}).collect();
+        assert_eq!(sorted(tree.dump_all_points()), moved);
+    }
+}
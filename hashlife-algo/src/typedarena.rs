@@ -28,9 +28,6 @@ impl<T> Arena<T>{
             newlast.last_mut().unwrap()
         }
     }
-    pub fn iter(&self)-> core::iter::Flatten<std::slice::Iter<'_, Vec<T>>>{
-        self.data.iter().into_iter().flatten()
-    }
     pub fn iter_mut(&mut self)->core::iter::Flatten<std::slice::IterMut<'_, Vec<T>>>{
         self.data.iter_mut().into_iter().flatten()
     }
@@ -0,0 +1,83 @@
+/*
+Glider synthesis helper: sends a spaceship into a target in every lane and
+timing where they meet, and lists the collisions grouped by what is left.
+With --examples, the starting pattern of the first collision of each
+outcome is saved so it can be looked at or built on.
+*/
+
+use std::fs;
+use std::path::Path;
+
+use hashlife_fast::{group_by_outcome, CollisionOptions, PatternMetadata};
+
+fn usage(program: &str) -> !{
+    eprintln!("usage: {} collide <target> <spaceship> [--threads N] [--max-generations N] \
+        [--max-period N] [--max-nodes N] [--lanes A:B] [--examples <dir>]", program);
+    std::process::exit(1);
+}
+
+fn parse_number<T: std::str::FromStr>(value: &str) -> T{
+    value.parse::<T>().unwrap_or_else(|_|{
+        eprintln!("expected a number, found '{}'", value);
+        std::process::exit(1);
+    })
+}
+
+// file name for an outcome, e.g. "2_block_glider"
+fn file_stem(outcome: &str) -> String{
+    outcome.split(|c: char|!c.is_ascii_alphanumeric()).filter(|w|!w.is_empty()).collect::<Vec<_>>().join("_")
+}
+
+pub fn collide(args: &[String]){
+    if args.len() < 4 || args[2].starts_with("--") || args[3].starts_with("--"){
+        usage(&args[0]);
+    }
+    let (target, _) = crate::load_pattern(&args[2]);
+    let (spaceship, _) = crate::load_pattern(&args[3]);
+    let mut options = CollisionOptions{
+        threads: std::thread::available_parallelism().map(|n|n.get()).unwrap_or(1),
+        ..CollisionOptions::default()
+    };
+    let mut examples = None;
+    let mut rest = args[4..].iter();
+    while let Some(flag) = rest.next(){
+        let value = rest.next().unwrap_or_else(||usage(&args[0]));
+        match flag.as_str(){
+            "--threads" => options.threads = std::cmp::max(1, parse_number(value)),
+            "--max-generations" => options.max_generations = parse_number(value),
+            "--max-period" => options.max_period = std::cmp::max(1, parse_number(value)),
+            "--max-nodes" => options.max_nodes = parse_number(value),
+            "--lanes" => options.lanes = match value.split_once(':'){
+                Some((lo, hi)) => Some((parse_number(lo), parse_number(hi))),
+                None => usage(&args[0]),
+            },
+            "--examples" => examples = Some(value.clone()),
+            _ => usage(&args[0]),
+        }
+    }
+    let collisions = target.collisions(&spaceship, &options).unwrap_or_else(||{
+        eprintln!("{}: not a spaceship with period at most {}", args[3], options.max_period);
+        std::process::exit(1);
+    });
+    if let Some(dir) = examples.as_ref(){
+        fs::create_dir_all(dir).unwrap_or_else(|err|{
+            eprintln!("{}: {}", dir, err);
+            std::process::exit(1);
+        });
+    }
+    let groups = group_by_outcome(&collisions);
+    for (outcome, group) in groups.iter(){
+        println!("{} {}", group.len(), outcome);
+        for c in group.iter(){
+            let generation = c.generation.map_or("-".to_string(), |g|g.to_string());
+            println!("    lane {} timing {} phase {} offset {} {} generation {}", c.lane, c.timing, c.phase, c.offset.x, c.offset.y, generation);
+        }
+        if let Some(dir) = examples.as_ref(){
+            let first = &group[0];
+            let start = target.with_projectile(&spaceship, first.phase, first.offset);
+            let fname = Path::new(dir).join(format!("{}.rle", file_stem(outcome)));
+            crate::save_pattern(fname.to_str().unwrap(), &start, &PatternMetadata::default());
+        }
+    }
+    println!("{} collisions, {} outcomes", collisions.len(), groups.len());
+}
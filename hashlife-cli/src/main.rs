@@ -4,7 +4,9 @@ use png::{Encoder,ColorType};
 
 
 use std::fs;
-use hashlife_fast::{TreeData,Point, parse_fle_file, write_rle};
+mod search;
+mod collide;
+use hashlife_fast::{TreeData,Point,PatternMetadata,PatternFormat, write_pattern, Equivalence, TimeSeries, Predecessor, PredecessorOptions, Exposure};
 
 fn dump_points_to_str(points: &Vec<Point>)->String{
     let mut sorted_points = points.clone();
@@ -26,27 +28,332 @@ fn save_png(fname:&str, xsize: usize, ysize: usize, data: &[u8]){
     writer.finish();
 }
 
+fn is_macrocell(fname: &str)->bool{
+    fname.ends_with(".mc") || fname.ends_with(".mc.gz")
+}
+
+fn load_pattern(fname: &str)->(TreeData, PatternMetadata){
+    let loaded = if is_macrocell(fname){
+        let data = fs::read(fname).unwrap();
+        TreeData::read_macrocell_bytes(&data).map(|tree|(tree, PatternMetadata::default()))
+    }
+    else{
+        let contents = fs::read_to_string(fname).unwrap();
+        TreeData::load_pattern_text(&contents)
+    };
+    loaded.unwrap_or_else(|err|{
+        eprintln!("{}: {}", fname, err);
+        std::process::exit(1);
+    })
+}
+
+fn save_pattern(fname: &str, tree: &TreeData, metadata: &PatternMetadata){
+    let res = if fname.ends_with(".mc.gz"){
+        fs::write(fname, tree.write_macrocell_gzip())
+    }
+    else{
+        match PatternFormat::from_file_name(fname).unwrap_or(PatternFormat::Rle){
+            PatternFormat::Macrocell => fs::write(fname, tree.write_macrocell()),
+            format => fs::write(fname, write_pattern(&tree.to_pattern(metadata), format)),
+        }
+    };
+    res.expect("failed to open output file for writing");
+}
+
+fn parse_arg_or(args: &[String], idx: usize, default: u64)->u64{
+    match args.get(idx){
+        Some(arg) => arg.parse::<u64>().unwrap_or_else(|_|{
+            eprintln!("expected a number, found '{}'", arg);
+            std::process::exit(1);
+        }),
+        None => default,
+    }
+}
+
+fn stabilise(args: &[String]){
+    // usage: stabilise <pattern> [max_generations] [max_period]
+    if args.len() < 3 || args.len() > 5{
+        eprintln!("usage: {} stabilise <pattern> [max_generations] [max_period]", args[0]);
+        std::process::exit(1);
+    }
+    let (tree, _) = load_pattern(&args[2]);
+    let max_generations = parse_arg_or(args, 3, 1000000);
+    let max_period = parse_arg_or(args, 4, 60);
+    match tree.find_stabilisation(max_generations, max_period){
+        Some(result) => println!("stabilised at generation {}, population {}, period {}",
+            result.generation, result.population, result.period),
+        None => {
+            println!("did not stabilise within {} generations", max_generations);
+            std::process::exit(2);
+        }
+    }
+}
+
+fn compare(args: &[String]){
+    // usage: compare <a> <b> [max_generations]
+    if args.len() < 4 || args.len() > 5{
+        eprintln!("usage: {} compare <a> <b> [max_generations]", args[0]);
+        std::process::exit(1);
+    }
+    let (a, _) = load_pattern(&args[2]);
+    let (b, _) = load_pattern(&args[3]);
+    let max_generations = parse_arg_or(args, 4, 1000);
+    println!("{} {}\n{} {}", a.fingerprint(), args[2], b.fingerprint(), args[3]);
+    let kinds = [
+        (Equivalence::Exact, "identical"),
+        (Equivalence::Translation, "same pattern, translated"),
+        (Equivalence::Symmetry, "same pattern, rotated or reflected"),
+    ];
+    for (equivalence, message) in kinds.iter(){
+        if a.same_pattern(&b, *equivalence){
+            println!("{}", message);
+            return;
+        }
+    }
+    if let Some(gen) = a.matching_phase(&b, Equivalence::Symmetry, max_generations){
+        println!("same pattern, {} is {} generations ahead", args[2], gen);
+    }
+    else if let Some(gen) = b.matching_phase(&a, Equivalence::Symmetry, max_generations){
+        println!("same pattern, {} is {} generations ahead", args[3], gen);
+    }
+    else{
+        println!("different patterns");
+        std::process::exit(2);
+    }
+}
+
+fn oscillator(args: &[String]){
+    // usage: oscillator <pattern> [max_period]
+    if args.len() < 3 || args.len() > 4{
+        eprintln!("usage: {} oscillator <pattern> [max_period]", args[0]);
+        std::process::exit(1);
+    }
+    let (tree, _) = load_pattern(&args[2]);
+    let max_period = parse_arg_or(args, 3, 1000);
+    match tree.oscillator_stats(max_period){
+        Some(stats) => {
+            println!("period {}\npopulation {}-{}\nrotor {}\nstator {}", stats.period, stats.min_population, stats.max_population, stats.rotor, stats.stator);
+            println!("heat {:.2}\nvolatility {:.2}\nstrict volatility {:.2}", stats.heat, stats.volatility, stats.strict_volatility);
+        },
+        None => {
+            println!("not an oscillator with period at most {}", max_period);
+            std::process::exit(2);
+        }
+    }
+}
+
+fn growth(args: &[String]){
+    // usage: growth [--horizon N] [--max-period N] <pattern>...
+    let usage = ||{
+        eprintln!("usage: {} growth [--horizon N] [--max-period N] <pattern>...", args[0]);
+        std::process::exit(1);
+    };
+    let mut horizon = 4096;
+    let mut max_period = 60;
+    let mut files = Vec::new();
+    let mut i = 2;
+    while i < args.len(){
+        match args[i].as_str(){
+            "--horizon" => {horizon = parse_arg_or(args, i + 1, 0); i += 1;},
+            "--max-period" => {max_period = parse_arg_or(args, i + 1, 0); i += 1;},
+            _ => files.push(&args[i]),
+        }
+        i += 1;
+    }
+    if files.is_empty() || max_period == 0{
+        usage();
+    }
+    // one line per pattern, so a collection can be sorted by class
+    for fname in files{
+        let (tree, _) = load_pattern(fname);
+        let growth = tree.classify_growth(horizon, max_period);
+        let period = growth.period.map_or("-".to_string(), |p|p.to_string());
+        println!("{}\tperiod {}\tgrowth {}\texponent {:.2}\t{}", growth.class, period, growth.growth, growth.exponent, fname);
+    }
+}
+
+fn predecessor(args: &[String]){
+    // usage: predecessor [--generations N] [--margin N] [--max-population N] [--max-conflicts N] <target> <out>
+    let usage = ||{
+        eprintln!("usage: {} predecessor [--generations N] [--margin N] [--max-population N] [--max-conflicts N] <target> <out>", args[0]);
+        std::process::exit(1);
+    };
+    let mut options = PredecessorOptions::default();
+    let mut files = Vec::new();
+    let mut i = 2;
+    while i < args.len(){
+        match args[i].as_str(){
+            "--generations" => {options.generations = parse_arg_or(args, i + 1, 0); i += 1;},
+            "--margin" => {options.margin = parse_arg_or(args, i + 1, 0) as i64; i += 1;},
+            "--max-population" => {options.max_population = Some(parse_arg_or(args, i + 1, 0)); i += 1;},
+            "--max-conflicts" => {options.max_conflicts = Some(parse_arg_or(args, i + 1, 0)); i += 1;},
+            _ => files.push(&args[i]),
+        }
+        i += 1;
+    }
+    if files.len() != 2 || options.generations == 0{
+        usage();
+    }
+    let (target, metadata) = load_pattern(files[0]);
+    match target.find_predecessor(&options){
+        Predecessor::Found(tree) => {
+            println!("found a predecessor of {} cells", tree.num_live_cells());
+            save_pattern(files[1], &tree, &metadata);
+        },
+        Predecessor::NoneInRegion => {
+            println!("no predecessor within {} cells of the pattern{}", options.margin, options.max_population.map_or(String::new(), |max|format!(" with at most {} cells", max)));
+            std::process::exit(2);
+        },
+        Predecessor::GaveUp => {
+            println!("gave up after {} conflicts", options.max_conflicts.unwrap_or(0));
+            std::process::exit(3);
+        },
+    }
+}
+
+fn exposure(args: &[String]){
+    // usage: exposure [--zoom N] [--step N] [--brightness F] [--envelope] <pattern> <generations> <out.png>
+    let usage = ||{
+        eprintln!("usage: {} exposure [--zoom N] [--step N] [--brightness F] [--envelope] <pattern> <generations> <out.png>", args[0]);
+        std::process::exit(1);
+    };
+    // widest image picked when no zoom is given
+    const MAX_IMAGE_SIZE: i64 = 1024;
+    let mut zoom = None;
+    let mut step = None;
+    let mut brightness = 1.0;
+    let mut envelope = false;
+    let mut files = Vec::new();
+    let mut i = 2;
+    while i < args.len(){
+        match args[i].as_str(){
+            "--zoom" => {zoom = Some(parse_arg_or(args, i + 1, 0) as u8); i += 1;},
+            "--step" => {step = Some(std::cmp::max(1, parse_arg_or(args, i + 1, 1))); i += 1;},
+            "--brightness" => {
+                let value = args.get(i + 1).map_or("", |b|b.as_str());
+                brightness = value.parse::<f64>().unwrap_or_else(|_|{
+                    eprintln!("expected a number, found '{}'", value);
+                    std::process::exit(1);
+                });
+                i += 1;
+            },
+            "--envelope" => envelope = true,
+            _ => files.push(&args[i]),
+        }
+        i += 1;
+    }
+    if files.len() != 3{
+        usage();
+    }
+    let (tree, _) = load_pattern(files[0]);
+    let generations = files[1].parse::<u64>().unwrap_or_else(|_|{
+        eprintln!("expected a number, found '{}'", files[1]);
+        std::process::exit(1);
+    });
+    let size = |(min, max): (Point, Point)|std::cmp::max(max.x - min.x, max.y - min.y);
+    let fit = |bounds|(0..63).find(|z|size(bounds) >> z < MAX_IMAGE_SIZE).unwrap() as u8;
+    // frame every cell alive in a frame. Without a zoom, a first guess from the start lets the
+    // bounds be taken with frames at least as close together as the ones of the final zoom
+    let first_zoom = zoom.unwrap_or_else(||fit(tree.bounding_box().unwrap_or_default()));
+    let bounds = tree.exposure_bounds(generations, step.unwrap_or(Exposure::default_step(first_zoom)));
+    // with no frames at all, the pattern as it is
+    let Some((min, max)) = bounds.or(tree.bounding_box()) else {
+        eprintln!("{}: the pattern is empty", files[0]);
+        std::process::exit(1);
+    };
+    let zoom = zoom.unwrap_or_else(||fit((min, max)));
+    // pixels lined up with the blocks of the tree
+    let pixel = 1i64 << zoom;
+    let offset = Point{x: min.x.div_euclid(pixel) * pixel, y: min.y.div_euclid(pixel) * pixel};
+    let (xsize, ysize) = (((max.x - offset.x) >> zoom) as usize + 1, ((max.y - offset.y) >> zoom) as usize + 1);
+    let exposure = tree.long_exposure(offset, xsize, ysize, zoom, generations, step.unwrap_or(Exposure::default_step(zoom)));
+    let image = if envelope {exposure.envelope()} else {exposure.to_grayscale(brightness)};
+    save_png(files[2], xsize, ysize, &image);
+    println!("{} frames, {}x{} pixels at zoom {}", exposure.frames, xsize, ysize, zoom);
+}
+
+// optional `--stats <file.csv|file.jsonl> [--stats-every N]` flags of the stepping mode, sampled
+// like TreeData::record_series
+fn parse_stats_flags(args: &[String])->(Option<String>, u64){
+    let mut stats_file = None;
+    let mut stats_every = 1<<9;
+    let mut rest = args.iter();
+    while let Some(flag) = rest.next(){
+        match (flag.as_str(), rest.next()){
+            ("--stats", Some(fname)) => stats_file = Some(fname.clone()),
+            ("--stats-every", Some(n)) => stats_every = std::cmp::max(1, n.parse::<u64>().unwrap_or_else(|_|{
+                eprintln!("expected a number, found '{}'", n);
+                std::process::exit(1);
+            })),
+            _ => {
+                eprintln!("usage: <pattern> <steps> <out> [--stats <file.csv|file.jsonl>] [--stats-every N]");
+                std::process::exit(1);
+            }
+        }
+    }
+    (stats_file, stats_every)
+}
+
 fn main() {
     let args: Vec<String> = env::args().collect();
+    if args.get(1).map(|s|s.as_str()) == Some("stabilise"){
+        stabilise(&args);
+        return;
+    }
+    if args.get(1).map(|s|s.as_str()) == Some("compare"){
+        compare(&args);
+        return;
+    }
+    if args.get(1).map(|s|s.as_str()) == Some("oscillator"){
+        oscillator(&args);
+        return;
+    }
+    if args.get(1).map(|s|s.as_str()) == Some("growth"){
+        growth(&args);
+        return;
+    }
+    if args.get(1).map(|s|s.as_str()) == Some("collide"){
+        collide::collide(&args);
+        return;
+    }
+    if args.get(1).map(|s|s.as_str()) == Some("predecessor"){
+        predecessor(&args);
+        return;
+    }
+    if args.get(1).map(|s|s.as_str()) == Some("exposure"){
+        exposure(&args);
+        return;
+    }
+    if args.get(1).map(|s|s.as_str()) == Some("search"){
+        search::search(&args);
+        return;
+    }
     println!("{:?}\n\n", args);
-    assert!(args.len() == 4);
+    assert!(args.len() >= 4);
     let in_filename = &args[1];
     let n_steps = args[2].parse::<u64>().unwrap();
     // let n_steps = args[2].parse::<u64>().unwrap();
     let out_filename = &args[3];
+    let (stats_file, stats_every) = parse_stats_flags(&args[4..]);
 
-    let contents = fs::read_to_string(in_filename).unwrap();
-    let points = parse_fle_file(&contents);
+    let (mut tree, metadata) = load_pattern(in_filename);
     let start_time = Instant::now();
-    let mut tree = TreeData::gather_all_points(&points);
     println!("finished gathering");
     let MAX_STEPS = 1<<9;
     let mut step_n = 0;
     let mut frame = 0;
     let xsize = 800;
     let ysize = 800;
+    let mut series = TimeSeries::new();
+    let end = tree.get_age() + n_steps;
+    if stats_file.is_some(){
+        series.record_due(&tree, stats_every, end);
+    }
     while step_n < n_steps{
-        let cur_steps = std::cmp::min(n_steps - step_n, MAX_STEPS);
+        let mut cur_steps = std::cmp::min(n_steps - step_n, MAX_STEPS - step_n % MAX_STEPS);
+        if stats_file.is_some(){
+            cur_steps = std::cmp::min(cur_steps, stats_every - step_n % stats_every);
+        }
         tree.step_forward(cur_steps);
         if tree.hash_count() > 15000000{
             let bef_garbage_tree_size = tree.hash_count();
@@ -55,6 +362,12 @@ fn main() {
             println!("Garbage collected, Bef: {},\t Aft: {}",bef_garbage_tree_size,aft_garbage_tree_size);
         }
         step_n += cur_steps;
+        if stats_file.is_some(){
+            series.record_due(&tree, stats_every, end);
+        }
+        if step_n % MAX_STEPS != 0 && step_n != n_steps{
+            continue;
+        }
         let t = start_time.elapsed().as_secs_f64();
         println!("reached step {} at time {} (avg {}) hash size {}",step_n,t,t/step_n as f64, tree.hash_count());
         let fname = format!("frames/step{:03}.png", frame);
@@ -62,17 +375,10 @@ fn main() {
         frame += 1;
     }
     println!("finished stepping");
-    let out_points = tree.dump_all_points();
-    println!("finished dumping");
-    let rle_tot_str = write_rle(&out_points);
-
-    // let orig_p_str = dump_points_to_str(&points);
-    // let new_p_str = dump_points_to_str(&out_points);
-    // fs::write("orig_points.txt", orig_p_str)
-    //     .expect("failed to open points.txt file for writing");
-    // fs::write("new_points.txt", new_p_str)
-    //     .expect("failed to open points.txt file for writing");
-
-    fs::write(out_filename, rle_tot_str)
-        .expect("failed to open output file for writing");
-}
\ No newline at end of file
+    if let Some(fname) = stats_file{
+        let contents = if fname.ends_with(".jsonl") {series.to_jsonl()} else {series.to_csv()};
+        fs::write(&fname, contents).expect("failed to open stats file for writing");
+    }
+    save_pattern(out_filename, &tree, &metadata);
+    println!("finished writing");
+}
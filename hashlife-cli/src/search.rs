@@ -0,0 +1,300 @@
+/*
+Parallel soup search: runs a range of seeded soups to stabilisation and
+takes a census of what is left, like apgsearch.
+
+Soup `i` has the seed `<root><i>`, so a range of soups always gives the
+same results. The range is cut into chunks that the threads take in turn,
+each with its own trees, and the chunk results are merged in order, which
+keeps the haul the same whatever the number of threads. After every batch
+of chunks the haul, the interesting soups and the next soup to run are
+written together to a single checkpoint file, and running the same command
+again carries on from there. The haul, interesting and rare files are
+written from the checkpoint afterwards, so a crash in between leaves at
+worst stale copies of them, never a haul that disagrees with the next soup.
+*/
+
+use std::fs;
+use std::path::Path;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Instant;
+
+use hashlife_fast::{Census, HaulHeader, SoupParams, Symmetry, TreeData, UNKNOWN_OBJECT};
+
+const HAUL_FILE: &str = "haul.txt";
+const INTERESTING_FILE: &str = "interesting.txt";
+const RARE_FILE: &str = "rare.txt";
+const CHECKPOINT_FILE: &str = "checkpoint.txt";
+// sections of the checkpoint after the settings and the next soup
+const INTERESTING_SECTION: &str = "@INTERESTING";
+const HAUL_SECTION: &str = "@HAUL";
+// soups a thread takes at a time, and chunks run between checkpoints per thread
+const CHUNK_SOUPS: u64 = 16;
+const CHUNKS_PER_THREAD: u64 = 16;
+// generations run past stabilisation so that escaping gliders get clear of the debris
+const SETTLE_GENERATIONS: u64 = 256;
+
+struct SearchOptions{
+    out_dir: String,
+    root: String,
+    start: u64,
+    count: u64,
+    threads: usize,
+    params: SoupParams,
+    max_generations: u64,
+    max_period: u64,
+    max_nodes: usize,
+    // soups that take at least this long to stabilise are reported
+    methuselah: u64,
+    // objects seen at most this many times are reported
+    rare: u64,
+}
+
+fn usage(program: &str) -> !{
+    eprintln!("usage: {} search <out_dir> [--root R] [--start N] [--count N] [--threads N] \
+        [--symmetry S] [--max-generations N] [--max-period N] [--max-nodes N] \
+        [--methuselah N] [--rare N]", program);
+    std::process::exit(1);
+}
+
+fn parse_number<T: std::str::FromStr>(value: &str) -> T{
+    value.parse::<T>().unwrap_or_else(|_|{
+        eprintln!("expected a number, found '{}'", value);
+        std::process::exit(1);
+    })
+}
+
+fn parse_options(args: &[String]) -> SearchOptions{
+    let out_dir = match args.get(2){
+        Some(dir) if !dir.starts_with("--") => dir.clone(),
+        _ => usage(&args[0]),
+    };
+    let mut opts = SearchOptions{
+        out_dir,
+        root: "hashlife_".to_string(),
+        start: 0,
+        count: 10000,
+        threads: std::thread::available_parallelism().map(|n|n.get()).unwrap_or(1),
+        params: SoupParams::default(),
+        max_generations: 100000,
+        max_period: 60,
+        max_nodes: 4000000,
+        methuselah: 10000,
+        rare: 1,
+    };
+    let mut rest = args[3..].iter();
+    while let Some(flag) = rest.next(){
+        let value = rest.next().unwrap_or_else(||usage(&args[0]));
+        match flag.as_str(){
+            "--root" => opts.root = value.clone(),
+            "--start" => opts.start = parse_number(value),
+            "--count" => opts.count = parse_number(value),
+            "--threads" => opts.threads = std::cmp::max(1, parse_number(value)),
+            "--symmetry" => opts.params.symmetry = value.parse::<Symmetry>().unwrap_or_else(|err|{
+                eprintln!("{}", err);
+                std::process::exit(1);
+            }),
+            "--max-generations" => opts.max_generations = parse_number(value),
+            "--max-period" => opts.max_period = std::cmp::max(1, parse_number(value)),
+            "--max-nodes" => opts.max_nodes = parse_number(value),
+            "--methuselah" => opts.methuselah = parse_number(value),
+            "--rare" => opts.rare = parse_number(value),
+            _ => usage(&args[0]),
+        }
+    }
+    opts
+}
+
+// settings that change the results, kept in the checkpoint to check a resumed search
+fn settings(opts: &SearchOptions) -> Vec<(&'static str, String)>{
+    vec![
+        ("root", opts.root.clone()),
+        ("symmetry", opts.params.symmetry.to_string()),
+        ("max_generations", opts.max_generations.to_string()),
+        ("max_period", opts.max_period.to_string()),
+        ("max_nodes", opts.max_nodes.to_string()),
+        ("methuselah", opts.methuselah.to_string()),
+    ]
+}
+
+#[derive(Default)]
+struct ChunkResult{
+    census: Census,
+    interesting: Vec<String>,
+}
+
+fn run_soup(index: u64, opts: &SearchOptions, result: &mut ChunkResult){
+    let soup_id = format!("{}{}", opts.root, index);
+    let soup = TreeData::random_soup(&soup_id, &opts.params);
+    match soup.find_stabilisation_within(opts.max_generations, opts.max_period, opts.max_nodes){
+        Some(stabilisation) => {
+            let mut tree = soup.pruned_tree();
+            tree.step_forward(stabilisation.generation + SETTLE_GENERATIONS);
+            let codes = result.census.add_soup(&tree, &soup_id, opts.max_period);
+            if stabilisation.generation >= opts.methuselah{
+                result.interesting.push(format!("{} methuselah {}", soup_id, stabilisation.generation));
+            }
+            if codes.iter().any(|c|c == UNKNOWN_OBJECT){
+                result.interesting.push(format!("{} unknown_object", soup_id));
+            }
+        },
+        None => result.interesting.push(format!("{} unstable", soup_id)),
+    }
+}
+
+// runs soups first..end on all threads, returning the chunk results in soup order
+fn run_batch(first: u64, end: u64, opts: &SearchOptions) -> Vec<ChunkResult>{
+    let n_chunks = (end - first).div_ceil(CHUNK_SOUPS) as usize;
+    let next_chunk = AtomicUsize::new(0);
+    let mut results: Vec<(usize, ChunkResult)> = std::thread::scope(|scope|{
+        let workers: Vec<_> = (0..opts.threads).map(|_|scope.spawn(||{
+            let mut done = Vec::new();
+            loop{
+                let chunk = next_chunk.fetch_add(1, Ordering::Relaxed);
+                if chunk >= n_chunks{
+                    return done;
+                }
+                let chunk_start = first + chunk as u64 * CHUNK_SOUPS;
+                let mut result = ChunkResult::default();
+                for index in chunk_start..std::cmp::min(chunk_start + CHUNK_SOUPS, end){
+                    run_soup(index, opts, &mut result);
+                }
+                done.push((chunk, result));
+            }
+        })).collect();
+        workers.into_iter().flat_map(|w|w.join().unwrap()).collect()
+    });
+    results.sort_by_key(|(chunk, _)|*chunk);
+    results.into_iter().map(|(_, result)|result).collect()
+}
+
+// writes through a temporary file so an interrupted search never leaves a half written file
+fn write_atomic(path: &Path, contents: &str){
+    let tmp = path.with_extension("tmp");
+    fs::write(&tmp, contents).and_then(|_|fs::rename(&tmp, path)).unwrap_or_else(|err|{
+        eprintln!("{}: {}", path.display(), err);
+        std::process::exit(1);
+    });
+}
+
+fn rare_report(census: &Census, rare: u64) -> String{
+    let mut s = String::new();
+    for (code, count) in census.table().iter().rev().take_while(|(_, count)|*count <= rare){
+        s.push_str(format!("{} {} {}\n", code, count, census.samples(code).join(" ")).as_str());
+    }
+    s
+}
+
+fn write_checkpoint(opts: &SearchOptions, next: u64, interesting: &str, haul: &str) -> String{
+    let mut s: String = settings(opts).iter().map(|(n, v)|format!("{} {}\n", n, v)).collect();
+    s.push_str(format!("next {}\n{}\n{}{}\n{}", next, INTERESTING_SECTION, interesting, HAUL_SECTION, haul).as_str());
+    s
+}
+
+// reads the checkpoint of an earlier run, giving the next soup, the interesting soups and the census
+fn read_checkpoint(path: &Path, opts: &SearchOptions) -> Option<(u64, String, Census)>{
+    let text = fs::read_to_string(path).ok()?;
+    let corrupt = ||-> !{
+        eprintln!("{}: not a search checkpoint", path.display());
+        std::process::exit(1);
+    };
+    let (progress, rest) = text.split_once(&format!("{}\n", INTERESTING_SECTION)).unwrap_or_else(||corrupt());
+    let (interesting, haul) = rest.split_once(&format!("{}\n", HAUL_SECTION)).unwrap_or_else(||corrupt());
+    let mut next = None;
+    for line in progress.lines(){
+        let (name, value) = line.split_once(' ').unwrap_or((line, ""));
+        if name == "next"{
+            next = Some(parse_number(value));
+        }
+        else if let Some((_, expected)) = settings(opts).iter().find(|(n, _)|*n == name){
+            if value != expected{
+                eprintln!("{} was written with {} {}, not {}", path.display(), name, value, expected);
+                std::process::exit(1);
+            }
+        }
+    }
+    let census = Census::read_haul(haul).unwrap_or_else(|err|{
+        eprintln!("{}: {}", path.display(), err);
+        std::process::exit(1);
+    }).1;
+    Some((next.unwrap_or_else(||corrupt()), interesting.to_string(), census))
+}
+
+pub fn search(args: &[String]){
+    let opts = parse_options(args);
+    run_search(&opts, opts.threads as u64 * CHUNKS_PER_THREAD * CHUNK_SOUPS);
+}
+
+// runs the search, writing a checkpoint every `batch_soups` soups
+fn run_search(opts: &SearchOptions, batch_soups: u64) -> Census{
+    let out_dir = Path::new(&opts.out_dir);
+    fs::create_dir_all(out_dir).expect("failed to create the output directory");
+    let header = HaulHeader{
+        version: format!("hashlife-rust-{}", env!("CARGO_PKG_VERSION")),
+        root: opts.root.clone(),
+        rule: "b3s23".to_string(),
+        symmetry: opts.params.symmetry.to_string(),
+    };
+    let end = opts.start + opts.count;
+    let mut census = Census::new();
+    let mut interesting = String::new();
+    let mut next = opts.start;
+    if let Some(resumed) = read_checkpoint(&out_dir.join(CHECKPOINT_FILE), opts){
+        (next, interesting, census) = resumed;
+        println!("resuming at soup {}", next);
+    }
+    let start_time = Instant::now();
+    let first = next;
+    while next < end{
+        let batch_end = std::cmp::min(next + batch_soups, end);
+        for result in run_batch(next, batch_end, opts){
+            census.merge(&result.census);
+            for line in result.interesting.iter(){
+                interesting.push_str(line);
+                interesting.push('\n');
+            }
+        }
+        next = batch_end;
+        let haul = census.write_haul(&header);
+        write_atomic(&out_dir.join(CHECKPOINT_FILE), &write_checkpoint(opts, next, &interesting, &haul));
+        write_atomic(&out_dir.join(HAUL_FILE), &haul);
+        write_atomic(&out_dir.join(INTERESTING_FILE), &interesting);
+        write_atomic(&out_dir.join(RARE_FILE), &rare_report(&census, opts.rare));
+        let t = start_time.elapsed().as_secs_f64();
+        println!("reached soup {} of {}, {:.1} soups/s, {} objects", next, end,
+            (next - first) as f64 / t, census.num_objects());
+    }
+    println!("search finished: {} soups, {} objects", census.num_soups(), census.num_objects());
+    census
+}
+
+#[cfg(test)]
+mod tests {
+    // Note this useful idiom: importing names from outer (for mod tests) scope.
+    use super::*;
+
+    fn options(dir: &Path, count: u64) -> SearchOptions{
+        let args: Vec<String> = ["hashlife-cli", "search", dir.to_str().unwrap(), "--count", &count.to_string(),
+            "--threads", "1", "--max-generations", "1000", "--max-period", "4"].iter().map(|a|a.to_string()).collect();
+        parse_options(&args)
+    }
+    fn read(dir: &Path, file: &str) -> String{
+        fs::read_to_string(dir.join(file)).unwrap()
+    }
+    #[test]
+    fn test_resume_after_crash() {
+        let base = std::env::temp_dir().join(format!("hashlife-search-test-{}", std::process::id()));
+        let (whole, resumed) = (base.join("whole"), base.join("resumed"));
+        run_search(&options(&whole, 2), 1);
+        run_search(&options(&resumed, 1), 1);
+        // a crash after the outputs of the second batch were written but before its checkpoint
+        for file in [HAUL_FILE, INTERESTING_FILE, RARE_FILE]{
+            fs::copy(whole.join(file), resumed.join(file)).unwrap();
+        }
+        let census = run_search(&options(&resumed, 2), 1);
+        assert_eq!(census.num_soups(), 2);
+        for file in [HAUL_FILE, INTERESTING_FILE, RARE_FILE, CHECKPOINT_FILE]{
+            assert_eq!(read(&resumed, file), read(&whole, file), "{}", file);
+        }
+        fs::remove_dir_all(&base).unwrap();
+    }
+}
@@ -1,7 +1,7 @@
 mod utils;
 
 use wasm_bindgen::prelude::*;
-use hashlife_fast::{TreeData,Point, parse_fle_file, write_rle,tile_bytes};
+use hashlife_fast::{TreeData,Point,DeltaSender,DeltaReceiver, parse_pattern, write_rle, write_rle_pattern,tile_bytes};
 use crate::utils::set_panic_hook;
 // // When the `wee_alloc` feature is enabled, use `wee_alloc` as the global
 // // allocator.
@@ -24,14 +24,58 @@ impl TreeDataWrapper{
     pub fn hash_count(&mut self)->usize{self.tree.hash_count()}
     pub fn get_age(&mut self)->u32{self.tree.get_age() as u32}
     pub fn get_rle(&self)->String{write_rle(&self.tree.dump_all_points())}
-    pub fn make_from_rle(rle:&str)->TreeDataWrapper{TreeDataWrapper { tree: TreeData::gather_all_points(&parse_fle_file(rle)) }}
+    // writes the current pattern with the comments, rule and position of the originally loaded file
+    pub fn get_rle_with_metadata(&self, source_rle:&str)->String{
+        let metadata = parse_pattern(source_rle).map(|pattern|pattern.metadata).unwrap_or_default();
+        write_rle_pattern(&self.tree.to_pattern(&metadata))
+    }
+    // accepts any of the supported text formats, not only RLE
+    pub fn make_from_rle(rle:&str)->Result<TreeDataWrapper, JsValue>{
+        let (tree, _) = TreeData::load_pattern_text(rle).map_err(|err|JsValue::from_str(&err.to_string()))?;
+        Ok(TreeDataWrapper { tree })
+    }
+    pub fn make_from_macrocell(data:&[u8])->Result<TreeDataWrapper, JsValue>{
+        let tree = TreeData::read_macrocell_bytes(data).map_err(|err|JsValue::from_str(&err.to_string()))?;
+        Ok(TreeDataWrapper { tree })
+    }
+    pub fn get_macrocell(&self)->String{self.tree.write_macrocell()}
+    // inclusive corners of the live cells as [min_x, min_y, max_x, max_y], empty when there are none
+    pub fn bounding_box(&self)->Vec<f64>{
+        match self.tree.bounding_box(){
+            Some((min, max)) => vec![min.x as f64, min.y as f64, max.x as f64, max.y as f64],
+            None => Vec::new(),
+        }
+    }
     pub fn pruned_tree(&self)->TreeDataWrapper{ TreeDataWrapper { tree: self.tree.pruned_tree() } }
     pub fn make_grayscale_map(&self, xstart:i32,ystart:i32, xsize: u32, ysize: u32, cellsize: u32, zoom: u8, brightness: f64) -> Vec<u8> {
         gray_to_rgba(&tile_bytes(&self.tree.make_grayscale_map(Point{x:xstart as i64,y:ystart as i64},xsize as usize,ysize as usize,zoom,brightness)[..],xsize as usize,cellsize as usize))
     }
     pub fn serialize_treerepr(&self)->Vec<u8>{ self.tree.serialize_treerepr() }
-    pub fn deserialize_treerepr(data: &[u8])->TreeDataWrapper{ TreeDataWrapper { tree: TreeData::deserialize_treerepr(data) } }
-
+    pub fn deserialize_treerepr(data: &[u8])->Result<TreeDataWrapper, JsValue>{
+        let tree = TreeData::deserialize_treerepr(data).map_err(|err|JsValue::from_str(&err.to_string()))?;
+        Ok(TreeDataWrapper { tree })
+    }
+    pub fn serialize_delta(&self, sender: &mut TreeSyncSender)->Vec<u8>{ self.tree.serialize_delta(&mut sender.sender) }
+    pub fn apply_delta(&mut self, receiver: &mut TreeSyncReceiver, data: &[u8])->Result<(), JsValue>{
+        self.tree.apply_delta(&mut receiver.receiver, data).map_err(|err|JsValue::from_str(&err.to_string()))
+    }
+}
+#[wasm_bindgen]
+pub struct TreeSyncSender{
+    sender: DeltaSender,
+}
+#[wasm_bindgen]
+impl TreeSyncSender{
+    pub fn new()->TreeSyncSender{TreeSyncSender{sender:DeltaSender::new()}}
+    pub fn reset(&mut self){self.sender.reset();}
+}
+#[wasm_bindgen]
+pub struct TreeSyncReceiver{
+    receiver: DeltaReceiver,
+}
+#[wasm_bindgen]
+impl TreeSyncReceiver{
+    pub fn new()->TreeSyncReceiver{TreeSyncReceiver{receiver:DeltaReceiver::new()}}
 }
 #[wasm_bindgen]
 extern {